@@ -2,20 +2,57 @@
 
 #[ink::contract]
 mod payment_contract {
-    use core::ops::Add;
-
     use ink::env:: hash;
     use ink::prelude::vec::Vec;
     use ink::{
         env::{
             block_timestamp,
+            call::{build_call, ExecutionInput, Selector},
             hash::{HashOutput, Sha2x256},
             DefaultEnvironment,
         },
         storage::Mapping,
     };
 
+    // Default number of OTP attempts per payment; the admin may change it with set_attempts_limit
     const ATTEMPTS_LIMIT: u8 = 3;
+    // Timestamps are in milliseconds: 86_400_000 ms = 24 hours
+    const DEFAULT_EXPIRY_TIME: Timestamp = 86_400_000;
+    // Minimum payment at deployment, 10^14 in the smallest unit
+    const DEFAULT_THRESHOLD: Balance = 100_000_000_000_000;
+    // Ticker of the native currency on 5ireChain, zero padded
+    const DEFAULT_SYMBOL: [u8; 8] = *b"5IRE\0\0\0\0";
+    // Largest clock skew grace the admin may configure (5 minutes)
+    const MAX_CLOCK_SKEW_GRACE: Timestamp = 300_000;
+    // Upper bound on the number of items any batch message accepts in one call
+    const MAX_BATCH_SIZE: u32 = 20;
+    // Index positions list_by_amount_range may visit in one call, matching or not
+    const MAX_AMOUNT_SCAN: u32 = 5 * MAX_BATCH_SIZE;
+    // Gas handed to an external notifier, validator or oracle so it cannot starve send_payment
+    const NOTIFIER_REF_TIME_LIMIT: u64 = 5_000_000_000;
+    // Default number of agents a receiver may approve
+    const DEFAULT_MAX_AGENTS: u32 = 5;
+    // Number of decimal digits in a generated OTP
+    const OTP_DIGITS: usize = 9;
+    // Smallest and largest OTP with exactly OTP_DIGITS digits
+    const OTP_MIN: u32 = 10u32.pow(OTP_DIGITS as u32 - 1);
+    const OTP_MAX: u32 = 10u32.pow(OTP_DIGITS as u32) - 1;
+    // Longest opaque routing hint a sender may pass to the receiver's notifier, in bytes
+    const MAX_CALLBACK_DATA_LEN: usize = 64;
+    // Entries kept in each payment's event log before the oldest is evicted
+    const MAX_PAYMENT_EVENTS: usize = 16;
+    // (event kind, timestamp) entries of one payment, oldest first
+    type PaymentLog = Vec<(u8, Timestamp)>;
+    // Client-chosen key that deduplicates retried sends
+    type IdempotencyKey = [u8; 32];
+    // SHA2-256 of a (receiver, reference) pair
+    type ReferenceKey = [u8; 32];
+    // (bucket, position) in the amount index where a range listing resumes
+    type AmountCursor = (u8, u32);
+    // Longest reference/memo a sender may attach, in bytes
+    const MAX_REFERENCE_LEN: usize = 64;
+    // Fees are expressed in basis points of the payment amount
+    const BPS_DENOMINATOR: Balance = 10_000;
 
     #[ink(storage)]
     pub struct PaymentContract {
@@ -24,6 +61,98 @@ mod payment_contract {
         admin: AccountId,
         expiry_time: Timestamp,
         salt: u64,
+        // Sum of amounts held for payments that are still pending
+        tracked_escrow: Balance,
+        // New payments and claims are rejected while paused
+        paused: bool,
+        // Minimum time between two OTP attempts on the same payment
+        min_attempt_gap: Timestamp,
+        status_counts: StatusCounts,
+        // Fee taken from each successful claim, in basis points
+        fee_bps: u16,
+        fee_recipient: AccountId,
+        rounding: RoundingMode,
+        // Test networks may use the zero address on purpose
+        allow_zero_address: bool,
+        // Per-receiver threshold overriding threshold_value
+        receiver_thresholds: Mapping<AccountId, Balance>,
+        // Senders exempt from any threshold
+        trusted_senders: Mapping<AccountId, bool>,
+        // Largest single payment each receiver is willing to accept
+        receive_caps: Mapping<AccountId, Balance>,
+        // Amount each sender currently has escrowed in pending payments
+        sender_locked: Mapping<AccountId, Balance>,
+        // Tolerance for validator clock differences when checking expiry
+        clock_skew_grace: Timestamp,
+        // Display ticker for the escrowed currency, zero padded
+        symbol: [u8; 8],
+        // OTPs compared against any payment, right or wrong, lenient repeats included;
+        // claims refused before the comparison (too soon, expired, wrong caller) do not count
+        total_otp_attempts: u64,
+        // Sum of every amount sent; reissues move existing funds and add nothing
+        total_volume: Balance,
+        // Contract each receiver wants called when a payment is sent to it
+        notifiers: Mapping<AccountId, AccountId>,
+        // Contract each receiver wants asked before accepting a payment
+        validators: Mapping<AccountId, AccountId>,
+        // Accounts each receiver has allowed to claim on its behalf
+        agents: Mapping<AccountId, Vec<AccountId>>,
+        max_agents: u32,
+        // When set, the global threshold is threshold_in_reference converted by this oracle
+        oracle: Option<AccountId>,
+        threshold_in_reference: Balance,
+        // Payments each receiver has been paid out on
+        receiver_successes: Mapping<AccountId, u32>,
+        // Extra wait after the claim window closes before the sender may refund
+        refund_delay: Timestamp,
+        // Blocks a payment must age before it can be claimed
+        min_confirmations: BlockNumber,
+        // Receivers whose payments start out Acknowledged
+        auto_acknowledge: Mapping<AccountId, bool>,
+        // Delay before staged threshold/expiry changes may be applied; 0 applies them at once
+        config_delay: Timestamp,
+        pending_config: PendingConfig,
+        // Most recent (event kind, timestamp) pairs per payment, oldest first
+        payment_logs: Mapping<Hash, PaymentLog>,
+        // Share of a cancelled payment kept as a cancellation fee, in basis points
+        cancel_fee_bps: u16,
+        // Payment ids grouped by the number of decimal digits in their amount; append-only,
+        // addressed as (bucket, position) so no bucket is ever loaded whole
+        amount_index: Mapping<(u8, u32), Hash>,
+        amount_bucket_len: Mapping<u8, u32>,
+        // Largest single payment accepted, if any
+        max_amount: Option<Balance>,
+        // Waiting or Acknowledged payments per receiver
+        receiver_pending: Mapping<AccountId, u32>,
+        // Sum of the amounts of all Waiting or Acknowledged payments
+        active_escrow: Balance,
+        // Snapshotted into each new payment; see PaymentInfo::min_attempts_before_refund
+        min_attempts_before_refund: u8,
+        // Most value the contract may hold in escrow at once, if capped
+        max_tvl: Option<Balance>,
+        // Payment created for each (sender, idempotency key)
+        idempotency_keys: Mapping<(AccountId, IdempotencyKey), Hash>,
+        // FIFO of payment ids in send order for tick; entries live at queue_head..queue_tail
+        outstanding_queue: Mapping<u64, Hash>,
+        queue_head: u64,
+        queue_tail: u64,
+        // Receivers the admin has KYC-verified, and the looser limits that apply to them
+        kyc_verified: Mapping<AccountId, bool>,
+        verified_max_amount: Option<Balance>,
+        verified_max_tvl: Option<Balance>,
+        // Payment ids per reference key; append-only like amount_index,
+        // so other senders cannot push a receiver's entries out
+        reference_index: Mapping<(ReferenceKey, u32), Hash>,
+        reference_len: Mapping<ReferenceKey, u32>,
+        // When set, resubmitting the same wrong OTP twice in a row costs no extra attempt
+        lenient_repeats: bool,
+        // Remaining claim time at or below which a wrong OTP also warns the receiver; 0 is off
+        warn_before: Timestamp,
+        // Snapshotted into each new payment as its attempts limit
+        attempts_limit: u8,
+        // Fees paid to the fee recipient so far, from claims and from cancellations
+        protocol_fees_collected: Balance,
+        cancel_fees_collected: Balance,
     }
 
     // ---------------------- Custom Struct---------------------------
@@ -33,18 +162,231 @@ mod payment_contract {
         feature = "std",
         derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
     )]
-
     pub struct PaymentInfo {
         sender: AccountId,
         receiver: AccountId,
         amount: Balance,
         otp: u32,
+        // Number of the next OTP attempt: 1 plus the wrong OTPs counted so far
         otp_attempts: u8,
+        // Attempts limit in force when the payment was sent
+        attempts_limit: u8,
         recorded_time: u64,
+        recorded_block: BlockNumber,
         status: PaymentStatus,
+        last_attempt_time: u64,
+        priority: bool,
+        // Deadline fixed at creation, so later expiry changes never affect it
+        expires_at: Timestamp,
+        // Time after which the sender may refund, also fixed at creation
+        refundable_at: Timestamp,
+        // OTP attempts the receiver must have made before the sender may refund on time;
+        // below it, both deadlines are pushed back by one claim window, once
+        min_attempts_before_refund: u8,
+        refundable: bool,
+        purge_on_failure: bool,
+        // Raised by the receiver; blocks refunds and cancels until the admin acts
+        disputed: bool,
+        // Sender's memo, stored as given; opaque bytes when reference_encrypted is set
+        reference: Vec<u8>,
+        reference_encrypted: bool,
+        // Only the receiver itself may claim, into its own account
+        strict_receiver: bool,
+        // Opaque bytes forwarded to the receiver's notifier
+        callback_data: Vec<u8>,
+        // Hash of the most recent wrong OTP, to spot accidental double submits
+        last_wrong_otp: Option<[u8; 32]>,
+    }
+
+    // Number of payments currently in each status; Acknowledged counts as waiting
+    #[derive(scale::Decode, scale::Encode, Debug, Clone, Default)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    struct StatusCounts {
+        waiting: u32,
+        success: u32,
+        refunded: u32,
+        expired: u32,
+        all_attempts_failed: u32,
+        forfeited: u32,
+        replaced: u32,
+    }
+
+    impl StatusCounts {
+        fn slot(&mut self, status: &PaymentStatus) -> &mut u32 {
+            match status {
+                PaymentStatus::Waiting | PaymentStatus::Acknowledged => &mut self.waiting,
+                PaymentStatus::Success => &mut self.success,
+                PaymentStatus::Refunded => &mut self.refunded,
+                PaymentStatus::Expired => &mut self.expired,
+                PaymentStatus::AllAttemptsFailed => &mut self.all_attempts_failed,
+                PaymentStatus::Forfeited => &mut self.forfeited,
+                PaymentStatus::Replaced => &mut self.replaced,
+            }
+        }
+
+        fn increment(&mut self, status: &PaymentStatus) {
+            let slot = self.slot(status);
+            *slot = slot.saturating_add(1);
+        }
+
+        fn decrement(&mut self, status: &PaymentStatus) {
+            let slot = self.slot(status);
+            *slot = slot.saturating_sub(1);
+        }
+    }
+
+    /// Admin changes staged under the config timelock, applied together once `effective_at` passes
+    #[derive(scale::Decode, scale::Encode, Debug, Clone, Default, PartialEq, Eq)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct PendingConfig {
+        pub threshold_value: Option<Balance>,
+        pub expiry_time: Option<Timestamp>,
+        pub refund_delay: Option<Timestamp>,
+        /// A shorter config delay, itself waiting out the current one
+        pub config_delay: Option<Timestamp>,
+        /// (oracle, threshold_in_reference) for `set_reference_threshold`
+        pub reference_threshold: Option<(Option<AccountId>, Balance)>,
+        pub effective_at: Timestamp,
+    }
+
+    impl PendingConfig {
+        fn is_empty(&self) -> bool {
+            self.threshold_value.is_none()
+                && self.expiry_time.is_none()
+                && self.refund_delay.is_none()
+                && self.config_delay.is_none()
+                && self.reference_threshold.is_none()
+        }
+    }
+
+    /// How fractional fee amounts are rounded
+    #[derive(scale::Decode, scale::Encode, Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub enum RoundingMode {
+        #[default]
+        Floor,
+        Ceil,
+        HalfUp,
+    }
+
+    /// Per-payment settings chosen by the sender at send time
+    #[derive(scale::Decode, scale::Encode, Debug, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct PaymentOptions {
+        /// Flags the payment for monitoring; exposed as a topic on the request event
+        pub priority: bool,
+        /// When false the sender can never take the funds back; if the payment fails
+        /// or expires unclaimed the funds are forfeited to the admin
+        pub refundable: bool,
+        /// When true the record is deleted once all OTP attempts fail; when false it is
+        /// kept with status `AllAttemptsFailed`
+        pub purge_on_failure: bool,
+        /// Free-form memo of at most `MAX_REFERENCE_LEN` bytes, public on chain
+        pub reference: Vec<u8>,
+        /// Marks `reference` as an encrypted blob; the contract never decrypts or checks it
+        pub reference_encrypted: bool,
+        /// Only the receiver may claim, and only into its own account; agent approvals
+        /// and payout redirection are ignored for this payment
+        pub strict_receiver: bool,
+        /// Opaque routing hint of at most `MAX_CALLBACK_DATA_LEN` bytes, forwarded
+        /// unchanged to the receiver's notifier
+        pub callback_data: Vec<u8>,
+        /// Client-chosen key; a later send by the same caller with the same key returns the
+        /// first payment's id, hands back the value attached to the retry, and creates nothing
+        pub idempotency_key: Option<IdempotencyKey>,
+        /// Stricter attempts limit for this payment, within `1..=` the global limit; with
+        /// `Some(1)` the first wrong OTP already fails the payment
+        pub attempts: Option<u8>,
+    }
+
+    impl Default for PaymentOptions {
+        fn default() -> Self {
+            Self {
+                priority: false,
+                refundable: true,
+                purge_on_failure: true,
+                reference: Vec::new(),
+                reference_encrypted: false,
+                strict_receiver: false,
+                callback_data: Vec::new(),
+                idempotency_key: None,
+                attempts: None,
+            }
+        }
+    }
+
+    /// Claim details safe to show a user; carries no OTP or other secret
+    #[derive(scale::Decode, scale::Encode, Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct PaymentVoucher {
+        pub payment_id: Hash,
+        pub amount: Balance,
+        pub receiver: AccountId,
+        pub expires_at: Timestamp,
+    }
+
+    /// Current configuration and running statistics, gathered for a single read
+    #[derive(scale::Decode, scale::Encode, Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct DashboardView {
+        pub admin: AccountId,
+        pub threshold_value: Balance,
+        pub expiry_time: Timestamp,
+        pub fee_bps: u16,
+        pub fee_recipient: AccountId,
+        pub paused: bool,
+        pub waiting: u32,
+        pub success: u32,
+        pub refunded: u32,
+        pub expired: u32,
+        pub all_attempts_failed: u32,
+        pub forfeited: u32,
+        pub replaced: u32,
+        pub total_otp_attempts: u64,
+        /// Sum of every amount sent so far
+        pub total_volume: Balance,
+        /// Amount escrowed in payments that are not yet settled
+        pub outstanding: Balance,
     }
 
     // ------------------------EVENT-----------------------------
+
+    // Events about a single payment, recorded in its on-chain log under KIND
+    trait PaymentEvent: ink::env::Event {
+        const KIND: u8;
+        fn payment_id(&self) -> Hash;
+    }
+
+    macro_rules! payment_events {
+        ($($event:ident = $kind:literal,)*) => {
+            $(impl PaymentEvent for $event {
+                const KIND: u8 = $kind;
+                fn payment_id(&self) -> Hash {
+                    self.payment_id
+                }
+            })*
+        };
+    }
+
+    payment_events! {
+        SecurePaymentRequested = 0,
+        SecurePaymentInfo = 1,
+        PaymentAcknowledged = 2,
+        PaymentPaidOut = 3,
+        PaymentOwnershipTransferred = 4,
+        DisputeRaised = 5,
+        PaymentRefunded = 6,
+        ClaimExpiringSoon = 7,
+    }
     #[ink(event)]
     pub struct SecurePaymentRequested {
         #[ink(topic)]
@@ -53,7 +395,9 @@ mod payment_contract {
         receiver: AccountId,
         amount: Balance,
         payment_id: Hash,
-        otp:u32
+        otp:u32,
+        #[ink(topic)]
+        priority: bool,
     }
 
     #[ink(event)]
@@ -67,6 +411,90 @@ mod payment_contract {
         status: PaymentStatus,
     }
 
+    #[ink(event)]
+    pub struct PaymentRefunded {
+        #[ink(topic)]
+        sender: AccountId,
+        #[ink(topic)]
+        receiver: AccountId,
+        amount: Balance,
+        payment_id: Hash,
+        reason: RefundReason,
+    }
+
+    #[ink(event)]
+    pub struct ClaimExpiringSoon {
+        #[ink(topic)]
+        receiver: AccountId,
+        payment_id: Hash,
+        remaining: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct RefundFailed {
+        #[ink(topic)]
+        recipient: AccountId,
+        payment_id: Hash,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct DisputeRaised {
+        #[ink(topic)]
+        receiver: AccountId,
+        payment_id: Hash,
+    }
+
+    #[ink(event)]
+    pub struct PaymentAcknowledged {
+        #[ink(topic)]
+        sender: AccountId,
+        #[ink(topic)]
+        receiver: AccountId,
+        payment_id: Hash,
+    }
+
+    #[ink(event)]
+    pub struct PaymentPaidOut {
+        #[ink(topic)]
+        receiver: AccountId,
+        #[ink(topic)]
+        payout_to: AccountId,
+        amount: Balance,
+        fee: Balance,
+        payment_id: Hash,
+    }
+
+    #[ink(event)]
+    pub struct PaymentOwnershipTransferred {
+        #[ink(topic)]
+        old_sender: AccountId,
+        #[ink(topic)]
+        new_sender: AccountId,
+        payment_id: Hash,
+    }
+
+    #[ink(event)]
+    pub struct ConfigChanged {
+        claim_window: Timestamp,
+        refund_delay: Timestamp,
+        #[ink(topic)]
+        admin: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct PauseToggled {
+        paused: bool,
+        #[ink(topic)]
+        admin: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ReconciledEscrow {
+        old_value: Balance,
+        new_value: Balance,
+    }
+
     #[ink(event)]
     pub struct ViewPaymentInfo {
         info: PaymentInfo,
@@ -108,6 +536,87 @@ mod payment_contract {
         AlreadyReceivedPayment,
         // Zero balance not accepted
         ZeroBalance,
+        // Batch input longer than MAX_BATCH_SIZE
+        BatchTooLarge,
+        // Contract is paused
+        ContractPaused,
+        // Operation requires the contract to be paused
+        NotPaused,
+        // Retried OTP before min_attempt_gap elapsed
+        AttemptTooSoon,
+        // Division by zero
+        DivByZero,
+        // Zero address not accepted
+        ZeroAddress,
+        // Fee above 100%
+        InvalidFee,
+        // Payment was sent as non-refundable
+        NonRefundable,
+        // OTP bytes are not OTP_DIGITS ASCII digits
+        InvalidOtpFormat,
+        // Clock skew grace above MAX_CLOCK_SKEW_GRACE
+        ClockSkewGraceTooLarge,
+        // Amount above the receiver's per-payment cap
+        ExceedsReceiverCap,
+        // Receiver already has max_agents approved
+        TooManyAgents,
+        // Expiry period must be non-zero
+        InvalidExpiry,
+        // Value changed since the caller last read it
+        StaleValue,
+        // Receiver's validator contract declined the payment
+        ReceiverRejected,
+        // Price oracle call failed, so the pegged threshold is unknown
+        OracleUnavailable,
+        // Payment is under dispute and waits for the admin
+        Disputed,
+        // Payment is younger than min_confirmations blocks
+        NotYetConfirmed,
+        // Reference longer than MAX_REFERENCE_LEN bytes
+        ReferenceTooLong,
+        // Pending config change is not yet due
+        TimelockNotElapsed,
+        // Callback data longer than MAX_CALLBACK_DATA_LEN bytes
+        CallbackDataTooLong,
+        // Range bounds are reversed
+        InvalidRange,
+        // Amount above the admin-set per-payment maximum
+        AboveMaxAmount,
+        // Payment would push tracked escrow above max_tvl
+        TvlCapExceeded,
+        // Native transfer out of the contract failed
+        TransferFailed,
+        // Attempts limit outside the allowed range
+        InvalidAttemptsLimit,
+    }
+
+    /// Why escrowed funds went back to the sender
+    #[derive(Debug, PartialEq, Eq, scale::Decode, scale::Encode, Clone, Copy)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum RefundReason {
+        // Sender cancelled while the payment was pending
+        CancelledBySender,
+        // Sender reclaimed it after the claim window and refund delay
+        ExpiredRefund,
+        // Receiver used up every OTP attempt
+        Rejected,
+    }
+
+    /// What a claim did. Every outcome is returned as `Ok`, because in ink! a message that
+    /// returns `Err` has all its storage writes and events reverted, and the attempt a wrong
+    /// OTP uses up must stick
+    #[derive(Debug, PartialEq, Eq, scale::Decode, scale::Encode, Clone, Copy)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum ClaimOutcome {
+        /// The OTP matched and the payout was made
+        Paid,
+        /// The OTP did not match; `attempts_left` more attempts are allowed
+        WrongOtp { attempts_left: u8 },
+        /// The OTP did not match and was the last attempt allowed: the payment failed and its
+        /// funds went back to the sender, or to the admin if it was non-refundable
+        AttemptsExhausted,
+        /// The claim deadline had passed; the payment is now Expired and awaits its refund
+        Expired,
     }
 
     #[derive(Debug, PartialEq, Eq, scale::Decode, scale::Encode, Clone)]
@@ -115,8 +624,7 @@ mod payment_contract {
         feature = "std",
         derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
     )]
-
-    enum PaymentStatus {
+    pub enum PaymentStatus {
         // Payment expired after 24 hours
         Expired,
         // Payment waiting receiver's input Key
@@ -127,6 +635,195 @@ mod payment_contract {
         Success,
         // Payment Refunded
         Refunded,
+        // Payment acknowledged by receiver, still waiting for the key
+        Acknowledged,
+        // Non-refundable payment released to admin after it failed or expired
+        Forfeited,
+        // Pending payment superseded by reissue_payment; its funds moved to the new payment
+        Replaced,
+    }
+
+    // Off-chain tally of emitted events per type, kept out of the wasm build
+    #[cfg(feature = "std")]
+    mod event_counter {
+        use std::{cell::RefCell, collections::BTreeMap};
+
+        std::thread_local! {
+            static COUNTS: RefCell<BTreeMap<&'static str, u32>> = const { RefCell::new(BTreeMap::new()) };
+        }
+
+        pub fn bump(event: &'static str) {
+            COUNTS.with(|counts| *counts.borrow_mut().entry(event).or_insert(0) += 1);
+        }
+
+        pub fn get(event: &'static str) -> u32 {
+            COUNTS.with(|counts| counts.borrow().get(event).copied().unwrap_or(0))
+        }
+    }
+
+    fn zero_address() -> AccountId {
+        AccountId::from([0u8; 32])
+    }
+
+    fn parse_otp(otp_bytes: &[u8]) -> Result<u32> {
+        if otp_bytes.len() != OTP_DIGITS {
+            return Err(Error::InvalidOtpFormat);
+        }
+        otp_bytes.iter().try_fold(0u32, |otp, byte| {
+            if !byte.is_ascii_digit() {
+                return Err(Error::InvalidOtpFormat);
+            }
+            otp.checked_mul(10)
+                .and_then(|otp| otp.checked_add(u32::from(byte - b'0')))
+                .ok_or(Error::InvalidOtpFormat)
+        })
+    }
+
+    // Whether a wrong OTP now would be the last one allowed: a limit of N allows exactly N
+    // attempts, and the Nth wrong OTP fails the payment. The counter never passes the limit,
+    // so it cannot overflow
+    fn attempts_exhausted(payment_info: &PaymentInfo) -> bool {
+        payment_info.otp_attempts >= payment_info.attempts_limit
+    }
+
+    // Whether the receiver has yet to make the attempts owed before the sender may take the
+    // funds back
+    fn awaiting_attempts(payment_info: &PaymentInfo) -> bool {
+        payment_info.otp_attempts.saturating_sub(1) < payment_info.min_attempts_before_refund
+    }
+
+    // Attempts still allowed, the one that would fail the payment included
+    fn attempts_left(payment_info: &PaymentInfo) -> u8 {
+        payment_info
+            .attempts_limit
+            .saturating_sub(payment_info.otp_attempts)
+            .saturating_add(1)
+    }
+
+    // get_pseudo_random draws uniformly from exactly this range
+    fn is_generated_otp(otp: u32) -> bool {
+        (OTP_MIN..=OTP_MAX).contains(&otp)
+    }
+
+    // Where a successful claim sends the payout (net of fee)
+    enum Payout {
+        Receiver,
+        To(AccountId),
+        Split(Vec<(AccountId, Balance)>),
+    }
+
+    impl Payout {
+        fn redirected(&self) -> bool {
+            !matches!(self, Payout::Receiver)
+        }
+
+        fn only_to(&self, receiver: AccountId) -> bool {
+            match self {
+                Payout::Receiver => true,
+                Payout::To(payout_to) => *payout_to == receiver,
+                Payout::Split(parts) => parts.iter().all(|(payout_to, _)| *payout_to == receiver),
+            }
+        }
+
+        // Destinations and amounts for a net payout of `amount`; a split must add up exactly
+        fn parts(self, receiver: AccountId, amount: Balance) -> Result<Vec<(AccountId, Balance)>> {
+            match self {
+                Payout::Receiver => Ok(Vec::from([(receiver, amount)])),
+                Payout::To(payout_to) => Ok(Vec::from([(payout_to, amount)])),
+                Payout::Split(parts) => {
+                    let total = parts.iter().try_fold(0 as Balance, |total, (_, part)| {
+                        total.checked_add(*part).ok_or(Error::BalanceMismatch)
+                    })?;
+                    if total != amount {
+                        return Err(Error::BalanceMismatch);
+                    }
+                    Ok(parts)
+                }
+            }
+        }
+    }
+
+    // Salted with the payment id so equal guesses on different payments look unrelated
+    fn otp_fingerprint(payment_id: Hash, otp: u32) -> [u8; 32] {
+        let mut fingerprint = <Sha2x256 as HashOutput>::Type::default();
+        ink::env::hash_encoded::<Sha2x256, _>(&(payment_id, otp), &mut fingerprint);
+        fingerprint
+    }
+
+    fn reference_key(receiver: AccountId, reference: &[u8]) -> ReferenceKey {
+        let mut key = <Sha2x256 as HashOutput>::Type::default();
+        ink::env::hash_encoded::<Sha2x256, _>(&(receiver, reference), &mut key);
+        key
+    }
+
+    fn is_pending(status: &PaymentStatus) -> bool {
+        matches!(status, PaymentStatus::Waiting | PaymentStatus::Acknowledged)
+    }
+
+    // Order-of-magnitude bucket for the amount index: its count of decimal digits
+    fn amount_bucket(amount: Balance) -> u8 {
+        amount.checked_ilog10().map_or(0, |log| log as u8 + 1)
+    }
+
+    /// Computes `value * num / den` rounded per `rounding` without overflowing on the product.
+    ///
+    /// The product is formed at 256 bits and divided back down, so `Overflow` means only
+    /// that the exact result does not fit in a `Balance`.
+    fn mul_div(value: Balance, num: Balance, den: Balance, rounding: RoundingMode) -> Result<Balance> {
+        if den == 0 {
+            return Err(Error::DivByZero);
+        }
+        let (quotient, remainder) = match value.checked_mul(num) {
+            Some(product) => (product / den, product % den),
+            None => {
+                let (high, low) = widening_mul(value, num);
+                div_wide(high, low, den).ok_or(Error::Overflow)?
+            }
+        };
+
+        let round_up = match rounding {
+            RoundingMode::Floor => false,
+            RoundingMode::Ceil => remainder > 0,
+            RoundingMode::HalfUp => remainder >= den - remainder,
+        };
+        quotient
+            .checked_add(round_up as Balance)
+            .ok_or(Error::Overflow)
+    }
+
+    // Full product of two u128 as (high, low) halves, from 64-bit limbs
+    fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+        const MASK: u128 = u64::MAX as u128;
+        let (a_high, a_low) = (a >> 64, a & MASK);
+        let (b_high, b_low) = (b >> 64, b & MASK);
+        let low_low = a_low * b_low;
+        let high_low = a_high * b_low;
+        let low_high = a_low * b_high;
+        // three terms below 2^64 each, so the sum cannot overflow
+        let middle = (low_low >> 64) + (high_low & MASK) + (low_high & MASK);
+        let low = (middle << 64) | (low_low & MASK);
+        let high = a_high * b_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+        (high, low)
+    }
+
+    // (quotient, remainder) of the 256-bit `high:low` by `den`, or None if the quotient
+    // needs more than 128 bits; plain shift-and-subtract long division
+    fn div_wide(high: u128, low: u128, den: u128) -> Option<(u128, u128)> {
+        if high >= den {
+            return None;
+        }
+        let (mut remainder, mut quotient) = (high, 0u128);
+        for bit in (0..128).rev() {
+            // the shifted-out top bit makes the true remainder at least 2^128 > den
+            let carry = remainder >> 127 == 1;
+            remainder = (remainder << 1) | ((low >> bit) & 1);
+            quotient <<= 1;
+            if carry || remainder >= den {
+                remainder = remainder.wrapping_sub(den);
+                quotient |= 1;
+            }
+        }
+        Some((quotient, remainder))
     }
 
     // ------------------------------Impl Contract---------------------------
@@ -134,12 +831,76 @@ mod payment_contract {
     impl PaymentContract {
         #[ink(constructor)]
         pub fn new(admin: AccountId) -> Self {
+            Self::init(admin)
+        }
+
+        /// Same as `new` but refuses the zero address as admin
+        #[ink(constructor)]
+        pub fn try_new(admin: AccountId) -> Result<Self> {
+            if admin == zero_address() {
+                return Err(Error::InvalidCaller);
+            }
+            Ok(Self::init(admin))
+        }
+
+        fn init(admin: AccountId) -> Self {
             Self {
                 payment_records: Mapping::default(),
-                threshold_value: u128::pow(10, 14),
+                threshold_value: DEFAULT_THRESHOLD,
                 admin,
-                expiry_time: 86_400_000,
+                expiry_time: DEFAULT_EXPIRY_TIME,
                 salt: 0,
+                tracked_escrow: 0,
+                paused: false,
+                min_attempt_gap: 0,
+                status_counts: StatusCounts::default(),
+                fee_bps: 0,
+                fee_recipient: admin,
+                rounding: RoundingMode::default(),
+                allow_zero_address: false,
+                receiver_thresholds: Mapping::default(),
+                trusted_senders: Mapping::default(),
+                receive_caps: Mapping::default(),
+                sender_locked: Mapping::default(),
+                clock_skew_grace: 0,
+                symbol: DEFAULT_SYMBOL,
+                total_otp_attempts: 0,
+                total_volume: 0,
+                notifiers: Mapping::default(),
+                validators: Mapping::default(),
+                agents: Mapping::default(),
+                max_agents: DEFAULT_MAX_AGENTS,
+                oracle: None,
+                threshold_in_reference: 0,
+                receiver_successes: Mapping::default(),
+                refund_delay: 0,
+                min_confirmations: 0,
+                auto_acknowledge: Mapping::default(),
+                config_delay: 0,
+                pending_config: PendingConfig::default(),
+                payment_logs: Mapping::default(),
+                cancel_fee_bps: 0,
+                amount_index: Mapping::default(),
+                amount_bucket_len: Mapping::default(),
+                max_amount: None,
+                receiver_pending: Mapping::default(),
+                active_escrow: 0,
+                min_attempts_before_refund: 0,
+                max_tvl: None,
+                idempotency_keys: Mapping::default(),
+                outstanding_queue: Mapping::default(),
+                queue_head: 0,
+                queue_tail: 0,
+                kyc_verified: Mapping::default(),
+                verified_max_amount: None,
+                verified_max_tvl: None,
+                reference_index: Mapping::default(),
+                reference_len: Mapping::default(),
+                lenient_repeats: false,
+                warn_before: 0,
+                attempts_limit: ATTEMPTS_LIMIT,
+                protocol_fees_collected: 0,
+                cancel_fees_collected: 0,
             }
         }
 
@@ -149,25 +910,89 @@ mod payment_contract {
             sender: AccountId,
             amount: Balance,
             otp: u32,
-        ) -> PaymentInfo {
-            PaymentInfo {
+            options: &PaymentOptions,
+        ) -> Result<PaymentInfo> {
+            if options.reference.len() > MAX_REFERENCE_LEN {
+                return Err(Error::ReferenceTooLong);
+            }
+            if options.callback_data.len() > MAX_CALLBACK_DATA_LEN {
+                return Err(Error::CallbackDataTooLong);
+            }
+            let attempts_limit = match options.attempts {
+                Some(attempts) if (1..=self.attempts_limit).contains(&attempts) => attempts,
+                Some(_) => return Err(Error::InvalidAttemptsLimit),
+                None => self.attempts_limit,
+            };
+
+            let recorded_time = block_timestamp::<DefaultEnvironment>();
+            let expires_at = recorded_time
+                .checked_add(self.expiry_time)
+                .ok_or(Error::Overflow)?;
+            let refundable_at = expires_at
+                .checked_add(self.refund_delay)
+                .ok_or(Error::Overflow)?;
+
+            Ok(PaymentInfo {
                 sender,
                 receiver,
                 amount,
                 otp,
                 otp_attempts: 1,
-                recorded_time: block_timestamp::<DefaultEnvironment>(),
+                attempts_limit,
+                recorded_time,
+                recorded_block: self.env().block_number(),
                 status: PaymentStatus::Waiting,
-            }
+                last_attempt_time: 0,
+                priority: options.priority,
+                expires_at,
+                refundable_at,
+                min_attempts_before_refund: self.min_attempts_before_refund,
+                refundable: options.refundable,
+                purge_on_failure: options.purge_on_failure,
+                disputed: false,
+                reference: options.reference.clone(),
+                reference_encrypted: options.reference_encrypted,
+                strict_receiver: options.strict_receiver,
+                callback_data: options.callback_data.clone(),
+                last_wrong_otp: None,
+            })
         }
       
         /// Handles payment_info from sender
         #[ink(message, payable)]
         pub fn send_payment(&mut self, receiver: AccountId, amount: Balance) -> Result<()> {
-            let caller = self.env().caller();
+            self.send_payment_with_options(receiver, amount, PaymentOptions::default())
+                .map(|_| ())
+        }
+
+        /// Runs every check `send_payment` would for the caller, assuming exactly `amount`
+        /// is attached, and returns the first failure without creating a payment
+        #[ink(message)]
+        pub fn can_send(&self, receiver: AccountId, amount: Balance) -> Result<()> {
+            self.check_send(self.env().caller(), receiver, amount, amount)
+        }
+
+        // Every condition send_payment enforces before creating a record, shared with can_send.
+        // Amount errors take precedence in this order: BalanceMismatch (amount differs from
+        // the value attached), ZeroBalance, BelowThresholdValue, AboveMaxAmount, ExceedsReceiverCap,
+        // TvlCapExceeded
+        fn check_send(
+            &self,
+            caller: AccountId,
+            receiver: AccountId,
+            amount: Balance,
+            amount_funded: Balance,
+        ) -> Result<()> {
+            if self.paused {
+                return Err(Error::ContractPaused);
+            }
+
+            self.ensure_not_zero(receiver)?;
+            if receiver == self.env().account_id() {
+                return Err(Error::InvalidReceiver);
+            }
 
             // Check the Locked amount
-            let amount_funded = self.env().transferred_value();
             if amount != amount_funded {
                 return Err(Error::BalanceMismatch);
             }
@@ -177,24 +1002,149 @@ mod payment_contract {
                 return Err(Error::ZeroBalance);
             }
 
-            // convert the units
-          
-
             // Check if amount exceeds the threshold value
-            if amount < self.threshold_value {
+            if amount < self.effective_threshold(caller, receiver)? {
                 return Err(Error::BelowThresholdValue);
             }
 
-            // create fixed length random OTP (9 digits)
+            let verified = self.kyc_verified.get(receiver).unwrap_or(false);
+            let (max_amount, max_tvl) = if verified {
+                // unset verified limits fall back to the global ones
+                (
+                    self.verified_max_amount.or(self.max_amount),
+                    self.verified_max_tvl.or(self.max_tvl),
+                )
+            } else {
+                (self.max_amount, self.max_tvl)
+            };
+
+            if max_amount.is_some_and(|max| amount > max) {
+                return Err(Error::AboveMaxAmount);
+            }
+
+            // Check the receiver's own per-payment cap
+            if self.receive_caps.get(receiver).is_some_and(|cap| amount > cap) {
+                return Err(Error::ExceedsReceiverCap);
+            }
+
+            let over_tvl = max_tvl.is_some_and(|max_tvl| {
+                self.tracked_escrow
+                    .checked_add(amount)
+                    .is_none_or(|total| total > max_tvl)
+            });
+            if over_tvl {
+                return Err(Error::TvlCapExceeded);
+            }
+
+            if !self.receiver_accepts(receiver, caller, amount) {
+                return Err(Error::ReceiverRejected);
+            }
+            Ok(())
+        }
+
+        /// Replaces a pending payment with a fresh one for the same receiver, amount and options:
+        /// new OTP, attempts reset, new deadlines. The old payment ends as Replaced and its
+        /// funds stay escrowed for the new payment, whose id is returned. The new payment must
+        /// pass every check `send_payment` applies today, except the attached value
+        #[ink(message)]
+        pub fn reissue_payment(&mut self, payment_id: Hash) -> Result<Hash> {
+            if self.paused {
+                return Err(Error::ContractPaused);
+            }
+            let mut payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            let caller = self.env().caller();
+            if caller != payment_info.sender {
+                return Err(Error::InvalidSender);
+            }
+            if payment_info.disputed {
+                return Err(Error::Disputed);
+            }
+            if !is_pending(&payment_info.status) {
+                return Err(Error::NotAllowed);
+            }
+
+            self.set_status(&mut payment_info, PaymentStatus::Replaced);
+            self.payment_records.insert(payment_id, &payment_info);
+            // checked once the old payment no longer counts against caps and the TVL limit
+            self.release_escrow(&payment_info);
+            self.check_send(caller, payment_info.receiver, payment_info.amount, payment_info.amount)?;
+            self.emit_payment(SecurePaymentInfo {
+                sender: payment_info.sender,
+                receiver: payment_info.receiver,
+                amount: payment_info.amount,
+                payment_id,
+                status: payment_info.status.clone(),
+            });
+
+            let options = PaymentOptions {
+                priority: payment_info.priority,
+                refundable: payment_info.refundable,
+                purge_on_failure: payment_info.purge_on_failure,
+                reference: payment_info.reference,
+                reference_encrypted: payment_info.reference_encrypted,
+                strict_receiver: payment_info.strict_receiver,
+                callback_data: payment_info.callback_data,
+                idempotency_key: None,
+                // never looser than the global limit now in force
+                attempts: Some(payment_info.attempts_limit.min(self.attempts_limit)),
+            };
+            self.record_payment(caller, payment_info.receiver, payment_info.amount, &options)
+        }
+
+        /// Same as `send_payment` with explicit per-payment options; returns the payment id
+        #[ink(message, payable)]
+        pub fn send_payment_with_options(
+            &mut self,
+            receiver: AccountId,
+            amount: Balance,
+            options: PaymentOptions,
+        ) -> Result<Hash> {
+            let caller = self.env().caller();
+
+            if let Some(key) = options.idempotency_key {
+                if let Some(existing) = self.idempotency_keys.get((caller, key)) {
+                    let retried_value = self.env().transferred_value();
+                    if retried_value > 0 {
+                        self.pay(caller, retried_value)?;
+                    }
+                    return Ok(existing);
+                }
+            }
+
+            self.check_send(caller, receiver, amount, self.env().transferred_value())?;
+            let payment_id = self.record_payment(caller, receiver, amount, &options)?;
+            self.total_volume = self.total_volume.saturating_add(amount);
+            Ok(payment_id)
+        }
+
+        // Creates and escrows a validated payment; the funds must already be in the contract
+        fn record_payment(
+            &mut self,
+            caller: AccountId,
+            receiver: AccountId,
+            amount: Balance,
+            options: &PaymentOptions,
+        ) -> Result<Hash> {
+            // create fixed length random OTP (OTP_DIGITS digits)
             let otp: u32 = self.get_pseudo_random();
 
             // Get payment_info and transaction_id
-            let payment_info = self.create_payment_info(receiver, caller, amount, otp);
+            let mut payment_info = self.create_payment_info(receiver, caller, amount, otp, options)?;
 
 
             // let transaction_id = self.get_transaction_id(&payment_info);
             let transaction_id = self.get_transaction_id(&payment_info);
 
+            // the id is derived from the Waiting record, so verify_id still matches
+            let auto_acknowledge = self.auto_acknowledge.get(receiver).unwrap_or(false);
+            if auto_acknowledge {
+                payment_info.status = PaymentStatus::Acknowledged;
+            }
+
             // Insert the payment record
             if self
                 .payment_records
@@ -205,16 +1155,45 @@ mod payment_contract {
                 return Err(Error::TxnIDAlreadExists);
             } 
             else {
+                self.tracked_escrow = self
+                    .tracked_escrow
+                    .checked_add(amount)
+                    .ok_or(Error::Overflow)?;
+                let locked = self
+                    .sender_locked_total(caller)
+                    .checked_add(amount)
+                    .ok_or(Error::Overflow)?;
+                self.sender_locked.insert(caller, &locked);
+                self.status_counts.increment(&payment_info.status);
+                self.adjust_pending(receiver, true);
+                self.active_escrow = self.active_escrow.saturating_add(amount);
+                self.index_amount(amount, transaction_id);
+                self.push_outstanding(transaction_id);
+                self.index_reference(receiver, &options.reference, transaction_id);
+                if let Some(key) = options.idempotency_key {
+                    self.idempotency_keys.insert((caller, key), &transaction_id);
+                }
+
                 // Emit event for payment record request
-                self.env().emit_event(SecurePaymentRequested {
+                self.emit_payment(SecurePaymentRequested {
                     sender: caller,
                     receiver,
                     amount,
                     payment_id: transaction_id,
-                    otp
+                    otp,
+                    priority: options.priority,
                 });
+                if auto_acknowledge {
+                    self.emit_payment(PaymentAcknowledged {
+                        sender: caller,
+                        receiver,
+                        payment_id: transaction_id,
+                    });
+                }
             }   
-            Ok(())
+
+            self.notify_receiver(receiver, transaction_id, caller, amount, &options.callback_data);
+            Ok(transaction_id)
         }
         fn get_pseudo_random(&mut self) -> u32 {
             let seed = self.env().block_timestamp();
@@ -228,23 +1207,19 @@ mod payment_contract {
 
             self.salt = self.salt.wrapping_add(1);
 
-            let mut part1 = output[0] as u32;
-            if part1 < 100 {
-                part1 = part1.wrapping_add(100);
-            }
-            let mut part2 = output[1] as u32;
-            if part2 < 100 {
-                part2 = part2.wrapping_add(100);
-            }
-            let mut part3 = output[2] as u32;
-            if part3 < 100 {
-                part3 = part3.wrapping_add(100);
-            }
-
-            let prefix = part1.wrapping_mul(1000).wrapping_add(part2);
-            prefix.wrapping_mul(1000).wrapping_add(part3)
+            // reducing 64 hash bits modulo the range size leaves a bias below 2^-32
+            let mut sample = [0u8; 8];
+            sample.copy_from_slice(&output[..8]);
+            let span = u64::from(OTP_MAX - OTP_MIN) + 1;
+            let offset = u64::from_be_bytes(sample) % span;
+            OTP_MIN + offset as u32
         }
 
+        /// Returns the full escrowed amount to the sender once the claim window and refund
+        /// delay have passed; no fee is ever deducted.
+        /// The record is marked Refunded and stored before the transfer, so any later call for
+        /// the same payment, re-entrant or not, fails the status check. The transfer itself is
+        /// a plain balance transfer that runs no code in the sender's account
         #[ink(message)]
         pub fn get_refund(&mut self, payment_id: Hash) -> Result<()> {
             let payment_info = self.payment_records.get(payment_id);
@@ -253,38 +1228,176 @@ mod payment_contract {
             }
             let mut payment_info = payment_info.unwrap();
 
-            let caller = self.env().caller();
-            if caller != payment_info.sender {
-                return Err(Error::InvalidSender);
+            self.check_refund(&payment_info)?;
+            self.refund_expired(payment_id, &mut payment_info)
+        }
+
+        /// Keeper entry point: walks up to `max_to_process` of the oldest outstanding payments
+        /// and refunds those whose refund time has passed. Settled, purged and non-refundable
+        /// payments are dropped from the queue (the admin still forfeits the latter by id);
+        /// disputed ones, ones not yet due and ones whose refund transfer fails move to the
+        /// back, the latter emitting RefundFailed. Returns how many queue entries were
+        /// processed; anyone may call it
+        #[ink(message)]
+        pub fn tick(&mut self, max_to_process: u32) -> Result<u32> {
+            if max_to_process > MAX_BATCH_SIZE {
+                return Err(Error::BatchTooLarge);
+            }
+            let mut processed = 0;
+            while processed < max_to_process && self.queue_head < self.queue_tail {
+                let Some(payment_id) = self.outstanding_queue.get(self.queue_head) else {
+                    break;
+                };
+                let Some(mut payment_info) = self.payment_records.get(payment_id) else {
+                    self.pop_outstanding();
+                    processed += 1;
+                    continue;
+                };
+                match self.refund_due(&payment_info) {
+                    Ok(()) => {
+                        self.pop_outstanding();
+                        // paying before the record changes leaves nothing to undo when the
+                        // transfer fails, so one bad payment cannot revert the whole tick
+                        match self.pay(payment_info.sender, payment_info.amount) {
+                            Ok(()) => self.record_expired_refund(payment_id, &mut payment_info),
+                            Err(_) => {
+                                // not put in the payment's log, which every retry would flood
+                                self.emit(RefundFailed {
+                                    recipient: payment_info.sender,
+                                    payment_id,
+                                    amount: payment_info.amount,
+                                });
+                                self.push_outstanding(payment_id)
+                            }
+                        }
+                    }
+                    Err(Error::NonRefundable) => self.pop_outstanding(),
+                    Err(_) if !is_pending(&payment_info.status)
+                        && payment_info.status != PaymentStatus::Expired =>
+                    {
+                        self.pop_outstanding()
+                    }
+                    Err(_) => {
+                        self.pop_outstanding();
+                        self.push_outstanding(payment_id);
+                    }
+                }
+                processed += 1;
+            }
+            Ok(processed)
+        }
+
+        /// Whether `get_refund` would currently succeed for the caller
+        #[ink(message)]
+        pub fn can_refund(&self, payment_id: Hash) -> Result<bool> {
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+            Ok(self.check_refund(&payment_info).is_ok())
+        }
+             
+        /// Releases an expired non-refundable payment to the admin
+        #[ink(message)]
+        pub fn release_forfeited(&mut self, payment_id: Hash) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
             }
 
-            if self.is_expired(payment_info.recorded_time) && payment_info.status!=PaymentStatus::Refunded && payment_info.status!=PaymentStatus::Success {
+            let mut payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
 
-                payment_info.status = PaymentStatus::Refunded;
+            let unclaimed = matches!(
+                payment_info.status,
+                PaymentStatus::Waiting | PaymentStatus::Acknowledged | PaymentStatus::Expired
+            );
+            if payment_info.refundable || !unclaimed || !self.is_expired(self.claim_deadline(&payment_info)) {
+                return Err(Error::NotAllowed);
+            }
+            if payment_info.disputed {
+                return Err(Error::Disputed);
+            }
 
-                self.env()
-                    .transfer(payment_info.sender, payment_info.amount)
-                    .unwrap();
+            self.set_status(&mut payment_info, PaymentStatus::Forfeited);
+            self.payment_records.insert(payment_id, &payment_info);
+            self.release_escrow(&payment_info);
 
-                self.payment_records.insert(payment_id, &payment_info);
+            self.pay(self.admin, payment_info.amount)?;
 
-                self.env().emit_event(SecurePaymentInfo {
-                    sender: payment_info.sender,
-                    receiver: payment_info.receiver,
-                    amount: payment_info.amount,
-                    payment_id,
-                    status: payment_info.status,
-                });
-                Ok(())
-            } else {
-                Err(Error::NotAllowed)
+            self.emit_payment(SecurePaymentInfo {
+                sender: payment_info.sender,
+                receiver: payment_info.receiver,
+                amount: payment_info.amount,
+                payment_id,
+                status: payment_info.status,
+            });
+            Ok(())
+        }
+
+         /// Handles payment_id & OTP from receiver for verification.
+        /// A wrong OTP, the last wrong OTP allowed and a claim past the deadline all return
+        /// `Ok` with the matching `ClaimOutcome`, so the attempt or status change is kept;
+        /// `Err` means nothing happened
+        #[ink(message)]
+        pub fn receive_payment(&mut self, payment_id: Hash, sent_otp: u32) -> Result<ClaimOutcome> {
+            self.claim(payment_id, sent_otp, Payout::Receiver)
+        }
+
+        /// Same as `receive_payment` but pays out to `payout_to` instead of the receiver
+        #[ink(message)]
+        pub fn receive_payment_to(
+            &mut self,
+            payment_id: Hash,
+            sent_otp: u32,
+            payout_to: AccountId,
+        ) -> Result<ClaimOutcome> {
+            self.ensure_not_zero(payout_to)?;
+            if payout_to == self.env().account_id() {
+                return Err(Error::InvalidReceiver);
             }
-      
+            self.claim(payment_id, sent_otp, Payout::To(payout_to))
         }
-             
-         /// Handles payment_id & OTP from receiver for verification
+
+        /// Same as `receive_payment` but spreads the payout over several accounts. The split
+        /// amounts must add up exactly to the payout after fee, or the claim fails with
+        /// `BalanceMismatch`; at most MAX_BATCH_SIZE splits. The fee is reported on the first
+        /// `PaymentPaidOut` event
         #[ink(message)]
-        pub fn receive_payment(&mut self, payment_id: Hash, sent_otp: u32) -> Result<()> {
+        pub fn receive_split(
+            &mut self,
+            payment_id: Hash,
+            sent_otp: u32,
+            splits: Vec<(AccountId, Balance)>,
+        ) -> Result<ClaimOutcome> {
+            if splits.len() > MAX_BATCH_SIZE as usize {
+                return Err(Error::BatchTooLarge);
+            }
+            for (payout_to, _) in &splits {
+                self.ensure_not_zero(*payout_to)?;
+                if *payout_to == self.env().account_id() {
+                    return Err(Error::InvalidReceiver);
+                }
+            }
+            self.claim(payment_id, sent_otp, Payout::Split(splits))
+        }
+
+        /// Same as `receive_payment` with the OTP given as ASCII digits, e.g. from an SMS gateway
+        #[ink(message)]
+        pub fn receive_payment_bytes(
+            &mut self,
+            payment_id: Hash,
+            otp_bytes: Vec<u8>,
+        ) -> Result<ClaimOutcome> {
+            let sent_otp = parse_otp(&otp_bytes)?;
+            self.claim(payment_id, sent_otp, Payout::Receiver)
+        }
+
+        fn claim(&mut self, payment_id: Hash, sent_otp: u32, payout: Payout) -> Result<ClaimOutcome> {
+            if self.paused {
+                return Err(Error::ContractPaused);
+            }
 
             let payment_info = self.payment_records.get(payment_id);
             
@@ -294,169 +1407,4889 @@ mod payment_contract {
 
             let mut payment_info = payment_info.unwrap();
 
+            // Checked before anything else touches the record, so a payment already cancelled,
+            // refunded or claimed earlier in the same block can never pay out again
             let status = payment_info.status.clone();
 
-            if status != PaymentStatus::Waiting || status == PaymentStatus::Refunded
+            if status != PaymentStatus::Waiting && status != PaymentStatus::Acknowledged
             { 
                 return Err(Error::AlreadyReceivedPayment);
             }
 
-            let caller = self.env().caller();
-            if caller != payment_info.receiver {
-                return Err(Error::InvalidReceiver);
-            }
+            self.check_claimant(&payment_info, self.env().caller(), &payout)?;
 
             // Check if payment has expired
-            if self.is_expired(payment_info.recorded_time) {
-                payment_info.status = PaymentStatus::Expired;
-                self.env().emit_event(SecurePaymentInfo {
+            if self.is_expired(self.claim_deadline(&payment_info)) {
+                self.set_status(&mut payment_info, PaymentStatus::Expired);
+                self.payment_records.insert(payment_id, &payment_info);
+                self.emit_payment(SecurePaymentInfo {
                     sender: payment_info.sender,
                     receiver: payment_info.receiver,
                     amount: payment_info.amount,
                     payment_id,
                     status: payment_info.status,
                 });
-                return Err(Error::TimeLimitExceeded);
+                return Ok(ClaimOutcome::Expired);
+            }
+
+            // one attempt per block, spaced by at least min_attempt_gap
+            let now = self.env().block_timestamp();
+            if now < self.next_attempt_time(&payment_info) {
+                return Err(Error::AttemptTooSoon);
             }
 
+            if !self.is_confirmed(&payment_info) {
+                return Err(Error::NotYetConfirmed);
+            }
+
+            self.total_otp_attempts = self
+                .total_otp_attempts
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+
             // match the otps
             if payment_info.otp != sent_otp {
-                use crate::payment_contract::ATTEMPTS_LIMIT;
+                let wrong_otp = otp_fingerprint(payment_id, sent_otp);
+                if self.lenient_repeats && payment_info.last_wrong_otp == Some(wrong_otp) {
+                    self.warn_if_expiring(&payment_info, payment_id, now);
+                    return Ok(ClaimOutcome::WrongOtp {
+                        attempts_left: attempts_left(&payment_info),
+                    });
+                }
+                payment_info.last_wrong_otp = Some(wrong_otp);
+                payment_info.last_attempt_time = now;
 
                 // if attempts exceeded the decided limit
-                if payment_info.otp_attempts > ATTEMPTS_LIMIT {
+                if attempts_exhausted(&payment_info) {
                     self.all_attempts_done( &mut payment_info, payment_id)
                 } else {
+                    self.warn_if_expiring(&payment_info, payment_id, now);
                     self.one_attempt_done( &mut payment_info, payment_id)
                 }
             } else {
 
-                // transfer amount to receiver
-                let amount=self.get_amount(&payment_info);
-                self.env()
-                    .transfer(payment_info.receiver, amount)
-                    .unwrap();
+                // transfer amount to receiver or the chosen payout account
+                // the fee is only realised here, on a successful claim
+                let (amount, fee) = self.get_amount(&payment_info)?;
+                self.check_payout(&payment_info, amount, fee)?;
+                let parts = payout.parts(payment_info.receiver, amount)?;
+                for (payout_to, part) in &parts {
+                    self.pay(*payout_to, *part)?;
+                }
+                if fee > 0 {
+                    self.pay(self.fee_recipient, fee)?;
+                    self.protocol_fees_collected =
+                        self.protocol_fees_collected.checked_add(fee).ok_or(Error::Overflow)?;
+                }
 
-                payment_info.status = PaymentStatus::Success;
+                self.set_status(&mut payment_info, PaymentStatus::Success);
                 
                 self.payment_records.insert(payment_id, &payment_info);
+                self.release_escrow(&payment_info);
 
                 // emit success event
-                self.env().emit_event(SecurePaymentInfo {
+                self.emit_payment(SecurePaymentInfo {
                     sender: payment_info.sender,
                     receiver: payment_info.receiver,
                     amount: payment_info.amount,
                     payment_id,
                     status: payment_info.status,
                 });
-                Ok(())
+                for (index, (payout_to, part)) in parts.into_iter().enumerate() {
+                    self.emit_payment(PaymentPaidOut {
+                        receiver: payment_info.receiver,
+                        payout_to,
+                        amount: part,
+                        fee: if index == 0 { fee } else { 0 },
+                        payment_id,
+                    });
+                }
+                Ok(ClaimOutcome::Paid)
             }
         }
 
-         #[ink(message)]
-        pub fn set_threshold_amount(&mut self, threshold_value: Balance) -> Result<()> {
-            if self.admin == self.env().caller() {
-                self.threshold_value = threshold_value;
-                Ok(())
-            } else {
-                Err(Error::InvalidCaller)
+        /// Error `receive_payment` would return for this OTP, `None` if it would pay out; no
+        /// attempt is consumed. Outcomes that `receive_payment` reports as `Ok` show up here
+        /// as `WrongOTP`, `AttemptsExceedLimit` and `TimeLimitExceeded`. Answers only the
+        /// receiver and the admin; this is not a secrecy guarantee, since a dry run may name
+        /// any caller and the OTP is readable from contract storage anyway
+        #[ink(message)]
+        pub fn preview_receive_error(&self, payment_id: Hash, otp: u32) -> Option<Error> {
+            self.preview_receive(payment_id, otp).err()
+        }
+
+        fn preview_receive(&self, payment_id: Hash, otp: u32) -> Result<()> {
+            if self.paused {
+                return Err(Error::ContractPaused);
+            }
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            let caller = self.env().caller();
+            if caller != payment_info.receiver && caller != self.admin {
+                return Err(Error::InvalidReceiver);
+            }
+
+            // the admin previews as if the receiver were claiming
+            if !is_pending(&payment_info.status) {
+                return Err(Error::AlreadyReceivedPayment);
+            }
+
+            if self.is_expired(self.claim_deadline(&payment_info)) {
+                return Err(Error::TimeLimitExceeded);
+            }
+            if self.env().block_timestamp() < self.next_attempt_time(&payment_info) {
+                return Err(Error::AttemptTooSoon);
+            }
+            if !self.is_confirmed(&payment_info) {
+                return Err(Error::NotYetConfirmed);
+            }
+
+            if payment_info.otp != otp {
+                let repeated = payment_info.last_wrong_otp == Some(otp_fingerprint(payment_id, otp));
+                return if attempts_exhausted(&payment_info)
+                    && !(self.lenient_repeats && repeated)
+                {
+                    Err(Error::AttemptsExceedLimit)
+                } else {
+                    Err(Error::WrongOTP)
+                };
             }
+            let (amount, fee) = self.get_amount(&payment_info)?;
+            self.check_payout(&payment_info, amount, fee)
         }
 
+        /// Status `receive_payment` would leave the payment in for this OTP, without
+        /// consuming an attempt. Answers the admin and whoever `receive_payment` would accept
+        /// as claimant, so agents are refused for strict_receiver payments. Refused while paused,
+        /// like `receive_payment`
         #[ink(message)]
-        pub fn view_payment_expiry_time(&self,payment_id: Hash) -> Timestamp{
-            let time = self.expiry_time;
-            let payment_info=self.payment_records.get(payment_id).unwrap();
-            let payment_created_time=payment_info.recorded_time;
-            payment_created_time.add(time)
+        pub fn simulate_receive(&self, payment_id: Hash, otp: u32) -> Result<PaymentStatus> {
+            if self.paused {
+                return Err(Error::ContractPaused);
+            }
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            let caller = self.env().caller();
+            if caller != self.admin {
+                self.check_claimant(&payment_info, caller, &Payout::Receiver)?;
+            }
+
+            if payment_info.status != PaymentStatus::Waiting
+                && payment_info.status != PaymentStatus::Acknowledged
+            {
+                return Err(Error::AlreadyReceivedPayment);
+            }
+
+            if self.is_expired(self.claim_deadline(&payment_info)) {
+                return Ok(PaymentStatus::Expired);
+            }
+
+            if self.env().block_timestamp() < self.next_attempt_time(&payment_info) {
+                return Err(Error::AttemptTooSoon);
+            }
+
+            if !self.is_confirmed(&payment_info) {
+                return Err(Error::NotYetConfirmed);
+            }
+
+            let repeated = payment_info.last_wrong_otp == Some(otp_fingerprint(payment_id, otp));
+            if payment_info.otp == otp {
+                Ok(PaymentStatus::Success)
+            } else if attempts_exhausted(&payment_info) && !(self.lenient_repeats && repeated) {
+                Ok(PaymentStatus::AllAttemptsFailed)
+            } else {
+                Ok(payment_info.status)
+            }
         }
 
+        /// Amount the receiver would be paid after the current fee, without revealing the OTP;
+        /// only the receiver and the admin may ask
         #[ink(message)]
-        pub fn set_expiry_period(&mut self, time: Timestamp) -> Result<()> {
-            if self.admin == self.env().caller() {
-                self.expiry_time = time;
-                Ok(())
-            } else {
-                Err(Error::InvalidCaller)
+        pub fn expected_amount(&self, payment_id: Hash) -> Result<Balance> {
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            let caller = self.env().caller();
+            if caller != payment_info.receiver && caller != self.admin {
+                return Err(Error::InvalidReceiver);
             }
+
+            let (payout, _fee) = self.get_amount(&payment_info)?;
+            Ok(payout)
         }
 
-        // #[ink(message)]
-        // pub fn view_current_time(&self) -> Timestamp {
-        //     let time = self.env().block_timestamp();
-        //     ink::env::debug_println!("{:?}", time);
-        //     time
-        // }
+        /// Lets the receiver flag a pending payment as disputed, blocking refunds and
+        /// cancels until the admin calls `settle_dispute` or `resolve_dispute`
+        #[ink(message)]
+        pub fn raise_dispute(&mut self, payment_id: Hash) -> Result<()> {
+            let mut payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
 
-         #[ink(message)]
-        pub fn view_payment_record(&self, payment_id: Hash) -> PaymentInfo {
-            let payment_info = self.payment_records.get(payment_id).unwrap();
-            payment_info
+            if self.env().caller() != payment_info.receiver {
+                return Err(Error::InvalidReceiver);
+            }
+
+            let pending = matches!(
+                payment_info.status,
+                PaymentStatus::Waiting | PaymentStatus::Acknowledged
+            );
+            if !pending || payment_info.disputed {
+                return Err(Error::NotAllowed);
+            }
+
+            payment_info.disputed = true;
+            self.payment_records.insert(payment_id, &payment_info);
+
+            self.emit_payment(DisputeRaised {
+                receiver: payment_info.receiver,
+                payment_id,
+            });
+            Ok(())
         }
 
+        /// Settles a dispute in the receiver's favour, paying out as a successful claim would
+        #[ink(message)]
+        pub fn settle_dispute(&mut self, payment_id: Hash) -> Result<()> {
+            let mut payment_info = self.disputed_payment(payment_id)?;
 
+            let (amount, fee) = self.get_amount(&payment_info)?;
+            self.check_payout(&payment_info, amount, fee)?;
 
-        fn all_attempts_done(&self, payment_info: &mut PaymentInfo, payment_id: Hash) -> Result<()> {
-            // refund payment to sender
-            self.env()
-                .transfer(payment_info.sender, payment_info.amount)
-                .unwrap();
-            self.payment_records.remove(payment_id);
+            payment_info.disputed = false;
+            self.set_status(&mut payment_info, PaymentStatus::Success);
+            self.payment_records.insert(payment_id, &payment_info);
+            self.release_escrow(&payment_info);
 
-            payment_info.status = PaymentStatus::AllAttemptsFailed;
+            self.pay(payment_info.receiver, amount)?;
+            if fee > 0 {
+                self.pay(self.fee_recipient, fee)?;
+                self.protocol_fees_collected =
+                    self.protocol_fees_collected.checked_add(fee).ok_or(Error::Overflow)?;
+            }
 
-            self.env().emit_event(SecurePaymentInfo {
+            self.emit_payment(SecurePaymentInfo {
                 sender: payment_info.sender,
                 receiver: payment_info.receiver,
                 amount: payment_info.amount,
                 payment_id,
-                status: payment_info.status.clone(),
+                status: payment_info.status,
+            });
+            self.emit_payment(PaymentPaidOut {
+                receiver: payment_info.receiver,
+                payout_to: payment_info.receiver,
+                amount,
+                fee,
+                payment_id,
             });
-            Err(Error::AttemptsExceedLimit)
+            Ok(())
         }
 
-        fn one_attempt_done(&self, payment_info: &mut PaymentInfo, payment_id: Hash) -> Result<()> {
-            // one more attempt done
-            let otps = payment_info.otp_attempts.checked_add(1);
-            if otps.is_none() {
-                return Err(Error::Overflow);
-            } else {
-                payment_info.otp_attempts = otps.unwrap();
+        /// Dismisses a dispute, leaving the payment as it was so refunds and cancels resume
+        #[ink(message)]
+        pub fn resolve_dispute(&mut self, payment_id: Hash) -> Result<()> {
+            let mut payment_info = self.disputed_payment(payment_id)?;
+            payment_info.disputed = false;
+            self.payment_records.insert(payment_id, &payment_info);
+            Ok(())
+        }
+
+        /// Lets the receiver signal that a waiting payment has been seen
+        #[ink(message)]
+        pub fn acknowledge_payment(&mut self, payment_id: Hash) -> Result<()> {
+            let mut payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            if self.env().caller() != payment_info.receiver {
+                return Err(Error::InvalidReceiver);
             }
 
-            payment_info.status = PaymentStatus::Waiting;
+            // only a waiting payment can be acknowledged, and only once
+            if payment_info.status != PaymentStatus::Waiting {
+                return Err(Error::NotAllowed);
+            }
+
+            if self.is_expired(self.claim_deadline(&payment_info)) {
+                return Err(Error::TimeLimitExceeded);
+            }
 
-            self.env().emit_event(SecurePaymentInfo {
+            self.set_status(&mut payment_info, PaymentStatus::Acknowledged);
+            self.payment_records.insert(payment_id, &payment_info);
+
+            self.emit_payment(PaymentAcknowledged {
                 sender: payment_info.sender,
                 receiver: payment_info.receiver,
-                amount: payment_info.amount,
                 payment_id,
-                status: payment_info.status.clone(),
             });
-            Err(Error::WrongOTP)
+            Ok(())
         }
 
-        fn is_expired(&self, recorded_time: u64) -> bool {
-            // 1 day has 86,400 seconds
-            let expiry_time=self.expiry_time;
-            block_timestamp::<DefaultEnvironment>() > recorded_time.checked_add(expiry_time).unwrap()
+        /// Opts the caller in or out of having new payments to it acknowledged on creation
+        #[ink(message)]
+        pub fn set_auto_acknowledge(&mut self, on: bool) -> Result<()> {
+            let receiver = self.env().caller();
+            if on {
+                self.auto_acknowledge.insert(receiver, &true);
+            } else {
+                self.auto_acknowledge.remove(receiver);
+            }
+            Ok(())
         }
-        
-        /// Returns the payment_id of payment_info
-        fn get_transaction_id(&self, payment_info: &PaymentInfo) -> Hash {
-            let encodable = payment_info;
-            let mut payment_id = <Sha2x256 as HashOutput>::Type::default();
-            ink::env::hash_encoded::<Sha2x256, _>(&encodable, &mut payment_id);
-            Hash::from(payment_id)
+
+        /// Hands refund rights on a pending payment to `new_sender`
+        #[ink(message)]
+        pub fn transfer_payment_ownership(
+            &mut self,
+            payment_id: Hash,
+            new_sender: AccountId,
+        ) -> Result<()> {
+            let mut payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            let old_sender = self.env().caller();
+            if old_sender != payment_info.sender {
+                return Err(Error::InvalidSender);
+            }
+            self.ensure_not_zero(new_sender)?;
+            // the contract could never claim its own refund back out of escrow
+            if new_sender == self.env().account_id() {
+                return Err(Error::InvalidReceiver);
+            }
+
+            if payment_info.status != PaymentStatus::Waiting
+                && payment_info.status != PaymentStatus::Acknowledged
+            {
+                return Err(Error::NotAllowed);
+            }
+
+            // move the locked amount along with the refund rights
+            let new_locked = self
+                .sender_locked_total(new_sender)
+                .checked_add(payment_info.amount)
+                .ok_or(Error::Overflow)?;
+            self.sender_locked.insert(new_sender, &new_locked);
+            let old_locked = self.sender_locked_total(old_sender);
+            self.sender_locked
+                .insert(old_sender, &old_locked.saturating_sub(payment_info.amount));
+
+            payment_info.sender = new_sender;
+            self.payment_records.insert(payment_id, &payment_info);
+
+            self.emit_payment(PaymentOwnershipTransferred {
+                old_sender,
+                new_sender,
+                payment_id,
+            });
+            Ok(())
         }
 
-        fn get_amount(&self,payment_info: &PaymentInfo)->Balance{
-            let encodable = payment_info;
-           let amount=encodable.amount;
-            amount
-           }
-    }
-      
+        /// Lets the sender withdraw a pending payment before it is claimed, less any
+        /// cancellation fee, once the receiver has made `min_attempts_before_refund` attempts.
+        /// Calls in a block run one after another, so against a racing claim whichever
+        /// executes first settles the payment and the other fails on the status check;
+        /// funds move exactly once
+        #[ink(message)]
+        pub fn cancel_payment(&mut self, payment_id: Hash) -> Result<()> {
+            self.cancel_one(payment_id)
+        }
+
+        /// Cancels several pending payments of the caller, reporting each outcome separately.
+        /// A payment that cannot be cancelled is reported without touching storage, but a
+        /// failed transfer fails the whole batch so no record is left refunded and unpaid
+        #[ink(message)]
+        pub fn cancel_payment_batch(&mut self, ids: Vec<Hash>) -> Result<Vec<Result<()>>> {
+            if ids.len() > MAX_BATCH_SIZE as usize {
+                return Err(Error::BatchTooLarge);
+            }
+            let mut results = Vec::with_capacity(ids.len());
+            for id in ids {
+                let result = self.cancel_one(id);
+                if result == Err(Error::TransferFailed) {
+                    return Err(Error::TransferFailed);
+                }
+                results.push(result);
+            }
+            Ok(results)
+        }
+
+        /// Sha2-256 of the SCALE-encoded OTP, for the sender or admin to check a candidate off-chain
+        #[ink(message)]
+        pub fn get_otp_hash(&self, payment_id: Hash) -> Result<[u8; 32]> {
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            let caller = self.env().caller();
+            if caller != payment_info.sender && caller != self.admin {
+                return Err(Error::InvalidCaller);
+            }
+
+            let mut otp_hash = <Sha2x256 as HashOutput>::Type::default();
+            ink::env::hash_encoded::<Sha2x256, _>(&payment_info.otp, &mut otp_hash);
+            Ok(otp_hash)
+        }
+
+        #[ink(message)]
+        pub fn get_tracked_escrow(&self) -> Balance {
+            self.tracked_escrow
+        }
+
+        /// Number of OTPs compared against any payment so far, right or wrong; claims refused
+        /// before the comparison are not counted
+        #[ink(message)]
+        pub fn get_total_attempts(&self) -> u64 {
+            self.total_otp_attempts
+        }
+
+        /// Sum of every amount sent so far, whatever became of the payments
+        #[ink(message)]
+        pub fn get_total_volume(&self) -> Balance {
+            self.total_volume
+        }
+
+        /// Whether `receiver` has at least one Waiting or Acknowledged payment; only the
+        /// receiver and the admin may ask
+        #[ink(message)]
+        pub fn has_pending(&self, receiver: AccountId) -> Result<bool> {
+            let caller = self.env().caller();
+            if caller != receiver && caller != self.admin {
+                return Err(Error::InvalidReceiver);
+            }
+            Ok(self.receiver_pending.get(receiver).unwrap_or(0) > 0)
+        }
+
+        /// Number of payments `receiver` has been paid out on
+        #[ink(message)]
+        pub fn receiver_success_count(&self, receiver: AccountId) -> u32 {
+            self.receiver_successes.get(receiver).unwrap_or(0)
+        }
+
+        /// Returns `(active_escrow, reclaimable_surplus)`: the sum of Waiting/Acknowledged
+        /// amounts, and the rest of the contract balance. The surplus includes expired payments
+        /// still awaiting refund as well as any funds sent outside `send_payment`
+        #[ink(message)]
+        pub fn escrow_breakdown(&self) -> (Balance, Balance) {
+            let surplus = self.env().balance().saturating_sub(self.active_escrow);
+            (self.active_escrow, surplus)
+        }
+
+        /// Total the sender currently has escrowed in pending payments
+        #[ink(message)]
+        pub fn sender_locked_total(&self, sender: AccountId) -> Balance {
+            self.sender_locked.get(sender).unwrap_or(0)
+        }
+
+        /// Returns (Waiting, Success, Refunded, Expired, AllAttemptsFailed) counts;
+        /// acknowledged payments are counted as waiting
+        #[ink(message)]
+        pub fn status_counts(&self) -> (u32, u32, u32, u32, u32) {
+            let counts = &self.status_counts;
+            (
+                counts.waiting,
+                counts.success,
+                counts.refunded,
+                counts.expired,
+                counts.all_attempts_failed,
+            )
+        }
+
+        /// Config and stats in one call; each field matches its individual getter
+        #[ink(message)]
+        pub fn dashboard(&self) -> DashboardView {
+            let counts = &self.status_counts;
+            DashboardView {
+                admin: self.admin,
+                threshold_value: self.threshold_value,
+                expiry_time: self.expiry_time,
+                fee_bps: self.fee_bps,
+                fee_recipient: self.fee_recipient,
+                paused: self.paused,
+                waiting: counts.waiting,
+                success: counts.success,
+                refunded: counts.refunded,
+                expired: counts.expired,
+                all_attempts_failed: counts.all_attempts_failed,
+                forfeited: counts.forfeited,
+                replaced: counts.replaced,
+                total_otp_attempts: self.total_otp_attempts,
+                total_volume: self.total_volume,
+                outstanding: self.tracked_escrow,
+            }
+        }
 
+         #[ink(message)]
+        pub fn set_threshold_amount(&mut self, threshold_value: Balance) -> Result<()> {
+            if self.admin == self.env().caller() {
+                if self.config_delay == 0 {
+                    self.threshold_value = threshold_value;
+                } else {
+                    self.pending_config.threshold_value = Some(threshold_value);
+                    self.restart_timelock()?;
+                }
+                Ok(())
+            } else {
+                Err(Error::InvalidCaller)
+            }
+        }
+
+        /// Sets the threshold only if it still equals `expected`, so concurrent admin
+        /// updates cannot silently overwrite each other. Under a config delay `expected` is
+        /// compared with the staged threshold when there is one
+        #[ink(message)]
+        pub fn compare_and_set_threshold(&mut self, expected: Balance, new: Balance) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            let current = self.pending_config.threshold_value.unwrap_or(self.threshold_value);
+            if current != expected {
+                return Err(Error::StaleValue);
+            }
+            self.set_threshold_amount(new)
+        }
+
+        #[ink(message)]
+        pub fn set_min_attempt_gap(&mut self, gap: Timestamp) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.min_attempt_gap = gap;
+            Ok(())
+        }
+
+        /// Number of blocks a payment must be old before it can be claimed, to ride out reorgs
+        #[ink(message)]
+        pub fn set_min_confirmations(&mut self, confirmations: BlockNumber) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.min_confirmations = confirmations;
+            Ok(())
+        }
+
+        /// Relaxes the zero-address checks on receivers and payout accounts (test networks only)
+        #[ink(message)]
+        pub fn set_allow_zero_address(&mut self, allow: bool) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.allow_zero_address = allow;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_paused(&mut self, paused: bool) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.paused = paused;
+
+            self.emit(PauseToggled {
+                paused,
+                admin: self.admin,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_paused(&self) -> bool {
+            self.paused
+        }
+
+        /// Overwrites tracked_escrow after drift; only allowed while paused
+        #[ink(message)]
+        pub fn reconcile_escrow(&mut self, new_value: Balance) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if !self.paused {
+                return Err(Error::NotPaused);
+            }
+
+            let old_value = self.tracked_escrow;
+            self.tracked_escrow = new_value;
+
+            self.emit(ReconciledEscrow {
+                old_value,
+                new_value,
+            });
+            Ok(())
+        }
+
+        /// Registers a contract to be called with
+        /// `on_payment_received(payment_id, sender, amount, callback_data: Vec<u8>)`
+        /// whenever a payment is sent to the caller
+        #[ink(message)]
+        pub fn register_notifier(&mut self, notifier: AccountId) -> Result<()> {
+            self.notifiers.insert(self.env().caller(), &notifier);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn clear_notifier(&mut self) -> Result<()> {
+            self.notifiers.remove(self.env().caller());
+            Ok(())
+        }
+
+        /// Registers (or clears with `None`) a contract queried with
+        /// `accept_payment(sender, amount) -> bool` before a payment to the caller is accepted.
+        /// A validator that fails to answer is treated as accepting, so it cannot block sends.
+        #[ink(message)]
+        pub fn set_acceptance_validator(&mut self, validator: Option<AccountId>) -> Result<()> {
+            let receiver = self.env().caller();
+            if let Some(validator) = validator {
+                self.validators.insert(receiver, &validator);
+            } else {
+                self.validators.remove(receiver);
+            }
+            Ok(())
+        }
+
+        /// Allows `agent` to claim the caller's payments into the caller's account
+        #[ink(message)]
+        pub fn approve_agent(&mut self, agent: AccountId) -> Result<()> {
+            let receiver = self.env().caller();
+            let mut agents = self.agents.get(receiver).unwrap_or_default();
+            if agents.contains(&agent) {
+                return Ok(());
+            }
+            if agents.len() >= self.max_agents as usize {
+                return Err(Error::TooManyAgents);
+            }
+            agents.push(agent);
+            self.agents.insert(receiver, &agents);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn revoke_agent(&mut self, agent: AccountId) -> Result<()> {
+            let receiver = self.env().caller();
+            let mut agents = self.agents.get(receiver).unwrap_or_default();
+            let index = agents
+                .iter()
+                .position(|approved| *approved == agent)
+                .ok_or(Error::NotAllowed)?;
+            agents.swap_remove(index);
+            if agents.is_empty() {
+                self.agents.remove(receiver);
+            } else {
+                self.agents.insert(receiver, &agents);
+            }
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_agents(&self, receiver: AccountId) -> Vec<AccountId> {
+            self.agents.get(receiver).unwrap_or_default()
+        }
+
+        /// Limits how many agents each receiver may approve; existing approvals are kept
+        #[ink(message)]
+        pub fn set_max_agents(&mut self, max_agents: u32) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.max_agents = max_agents;
+            Ok(())
+        }
+
+        /// Caps (or uncaps with `None`) the amount of any single payment sent to the caller
+        #[ink(message)]
+        pub fn set_my_receive_cap(&mut self, cap: Option<Balance>) -> Result<()> {
+            let caller = self.env().caller();
+            if let Some(cap) = cap {
+                self.receive_caps.insert(caller, &cap);
+            } else {
+                self.receive_caps.remove(caller);
+            }
+            Ok(())
+        }
+
+        /// Sets or clears (`None`) the maximum for a single payment; also applies to verified
+        /// receivers unless `set_verified_limits` gives them their own
+        #[ink(message)]
+        pub fn set_max_amount(&mut self, max_amount: Option<Balance>) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.max_amount = max_amount;
+            Ok(())
+        }
+
+        /// Sets or clears (`None`) a cap on the total value escrowed at once, checked on every
+        /// send unless `set_verified_limits` gives verified receivers their own; payments
+        /// settled, refunded or forfeited free room for new ones
+        #[ink(message)]
+        pub fn set_max_tvl(&mut self, max_tvl: Option<Balance>) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.max_tvl = max_tvl;
+            Ok(())
+        }
+
+        /// Number of OTP attempts a new payment allows; the last of them, if wrong, fails it
+        #[ink(message)]
+        pub fn get_attempts_limit(&self) -> u8 {
+            self.attempts_limit
+        }
+
+        /// Sets the attempts limit for new payments; existing payments keep their own
+        #[ink(message)]
+        pub fn set_attempts_limit(&mut self, limit: u8) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if limit == 0 {
+                return Err(Error::InvalidAttemptsLimit);
+            }
+            self.attempts_limit = limit;
+            Ok(())
+        }
+
+        /// When on, a wrong OTP identical to the payment's previous wrong OTP is rejected
+        /// without using up an attempt, forgiving accidental double submits
+        #[ink(message)]
+        pub fn set_lenient_repeats(&mut self, lenient: bool) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.lenient_repeats = lenient;
+            Ok(())
+        }
+
+        /// Sets how close to its claim deadline a payment must be for a wrong OTP to also emit
+        /// ClaimExpiringSoon; 0 turns the warning off
+        #[ink(message)]
+        pub fn set_warn_before(&mut self, warn_before: Timestamp) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.warn_before = warn_before;
+            Ok(())
+        }
+
+        /// Marks a receiver as KYC-verified, moving its incoming payments to the verified limits
+        #[ink(message)]
+        pub fn set_kyc_status(&mut self, receiver: AccountId, verified: bool) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if verified {
+                self.kyc_verified.insert(receiver, &true);
+            } else {
+                self.kyc_verified.remove(receiver);
+            }
+            Ok(())
+        }
+
+        /// Per-payment maximum and TVL cap used instead of `max_amount`/`max_tvl` for payments
+        /// to verified receivers; `None` keeps the global limit for them
+        #[ink(message)]
+        pub fn set_verified_limits(
+            &mut self,
+            max_amount: Option<Balance>,
+            max_tvl: Option<Balance>,
+        ) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.verified_max_amount = max_amount;
+            self.verified_max_tvl = max_tvl;
+            Ok(())
+        }
+
+        /// Sets or clears (`None`) a receiver-specific threshold
+        #[ink(message)]
+        pub fn set_receiver_threshold(
+            &mut self,
+            receiver: AccountId,
+            threshold: Option<Balance>,
+        ) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if let Some(threshold) = threshold {
+                self.receiver_thresholds.insert(receiver, &threshold);
+            } else {
+                self.receiver_thresholds.remove(receiver);
+            }
+            Ok(())
+        }
+
+        /// Per-receiver threshold overrides in input order, `None` where the global one applies
+        #[ink(message)]
+        pub fn get_receiver_thresholds(
+            &self,
+            receivers: Vec<AccountId>,
+        ) -> Result<Vec<Option<Balance>>> {
+            if receivers.len() > MAX_BATCH_SIZE as usize {
+                return Err(Error::BatchTooLarge);
+            }
+            Ok(receivers
+                .into_iter()
+                .map(|receiver| self.receiver_thresholds.get(receiver))
+                .collect())
+        }
+
+        /// Exempts a sender from all thresholds, or removes the exemption
+        #[ink(message)]
+        pub fn set_trusted_sender(&mut self, sender: AccountId, trusted: bool) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if trusted {
+                self.trusted_senders.insert(sender, &true);
+            } else {
+                self.trusted_senders.remove(sender);
+            }
+            Ok(())
+        }
+
+        /// Minimum amount `send_payment` enforces for this sender/receiver pair.
+        /// Fails with `OracleUnavailable` when the pegged global threshold applies and
+        /// the oracle cannot be reached
+        #[ink(message)]
+        pub fn effective_threshold(&self, sender: AccountId, receiver: AccountId) -> Result<Balance> {
+            if self.trusted_senders.get(sender).unwrap_or(false) {
+                return Ok(0);
+            }
+            match self.receiver_thresholds.get(receiver) {
+                Some(threshold) => Ok(threshold),
+                None => self.global_threshold(),
+            }
+        }
+
+        /// Pegs the global threshold to `threshold_in_reference` units, converted to native
+        /// units by `oracle` at send time via `to_native(amount) -> Balance`.
+        /// Passing `None` returns to the fixed `threshold_value`.
+        /// Staged for `apply_pending_config` while a config delay is set
+        #[ink(message)]
+        pub fn set_reference_threshold(
+            &mut self,
+            oracle: Option<AccountId>,
+            threshold_in_reference: Balance,
+        ) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if self.config_delay > 0 {
+                self.pending_config.reference_threshold = Some((oracle, threshold_in_reference));
+                return self.restart_timelock();
+            }
+            self.oracle = oracle;
+            self.threshold_in_reference = threshold_in_reference;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn view_payment_expiry_time(&self,payment_id: Hash) -> Timestamp{
+            let payment_info=self.payment_records.get(payment_id).unwrap();
+            self.claim_deadline(&payment_info)
+        }
+
+        /// Time elapsed since the payment was recorded, zero if the clock reads earlier
+        #[ink(message)]
+        pub fn payment_age(&self, payment_id: Hash) -> Result<Timestamp> {
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            Ok(self
+                .env()
+                .block_timestamp()
+                .saturating_sub(payment_info.recorded_time))
+        }
+
+        /// Earliest timestamp at which `receive_payment` will accept the next attempt
+        #[ink(message)]
+        pub fn next_attempt_allowed_at(&self, payment_id: Hash) -> Result<Timestamp> {
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            Ok(self
+                .next_attempt_time(&payment_info)
+                .max(self.env().block_timestamp()))
+        }
+
+        /// Time left before the payment expires; a clock earlier than its creation
+        /// reports the full expiry window
+        #[ink(message)]
+        pub fn time_until_expiry(&self, payment_id: Hash) -> Result<Timestamp> {
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            let now = self.env().block_timestamp().max(payment_info.recorded_time);
+            Ok(self.claim_deadline(&payment_info).saturating_sub(now))
+        }
+
+        /// Sets the claim fee in basis points and the account that receives it.
+        /// Fees apply to successful claims only; refunds return the full amount and cancels
+        /// are charged only the separate `cancel_fee_bps`
+        #[ink(message)]
+        pub fn set_fee(&mut self, fee_bps: u16, fee_recipient: AccountId) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if fee_bps as Balance > BPS_DENOMINATOR {
+                return Err(Error::InvalidFee);
+            }
+            // a zero recipient would burn every fee
+            if (fee_bps > 0 || self.cancel_fee_bps > 0) && fee_recipient == zero_address() {
+                return Err(Error::ZeroAddress);
+            }
+            self.fee_bps = fee_bps;
+            self.fee_recipient = fee_recipient;
+            Ok(())
+        }
+
+        /// Minimum OTP attempts the receiver must have made before the sender can refund at
+        /// the normal time; otherwise claim and refund deadlines move back one claim window
+        /// and the sender cannot cancel. Applies to new payments only
+        #[ink(message)]
+        pub fn set_min_attempts_before_refund(&mut self, min_attempts: u8) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.min_attempts_before_refund = min_attempts;
+            Ok(())
+        }
+
+        /// Sets the fee taken from sender cancellations, paid to the fee recipient;
+        /// expiry refunds and dispute outcomes stay fee free
+        #[ink(message)]
+        pub fn set_cancel_fee(&mut self, cancel_fee_bps: u16) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if cancel_fee_bps as Balance > BPS_DENOMINATOR {
+                return Err(Error::InvalidFee);
+            }
+            if cancel_fee_bps > 0 && self.fee_recipient == zero_address() {
+                return Err(Error::ZeroAddress);
+            }
+            self.cancel_fee_bps = cancel_fee_bps;
+            Ok(())
+        }
+
+        /// Current OTP salt counter, for auditing generated codes against the seed state.
+        /// Answers only the admin, but the counter sits in public contract storage, so this
+        /// keeps nothing secret and the OTP must not rely on the salt being unknown
+        #[ink(message)]
+        pub fn get_salt(&self) -> Result<u64> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            Ok(self.salt)
+        }
+
+        /// Cancellation fee in basis points
+        #[ink(message)]
+        pub fn get_cancel_fee(&self) -> u16 {
+            self.cancel_fee_bps
+        }
+
+        /// Total fees paid to the fee recipient so far, of every kind
+        #[ink(message)]
+        pub fn get_total_fees(&self) -> Balance {
+            self.protocol_fees_collected
+                .saturating_add(self.cancel_fees_collected)
+        }
+
+        /// Fees collected so far as (claim fees, cancellation fees); expiry refunds and
+        /// dispute refunds never carry a fee
+        #[ink(message)]
+        pub fn get_fee_breakdown(&self) -> (Balance, Balance) {
+            (self.protocol_fees_collected, self.cancel_fees_collected)
+        }
+
+        #[ink(message)]
+        pub fn set_rounding_mode(&mut self, rounding: RoundingMode) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.rounding = rounding;
+            Ok(())
+        }
+
+        /// Returns (fee_bps, fee_recipient, rounding)
+        #[ink(message)]
+        pub fn get_fee_config(&self) -> (u16, AccountId, RoundingMode) {
+            (self.fee_bps, self.fee_recipient, self.rounding)
+        }
+
+        /// Sets the expiry for new payments in milliseconds; existing payments keep their own deadline.
+        /// Staged for `apply_pending_config` while a config delay is set
+        #[ink(message)]
+        pub fn set_expiry_period(&mut self, time: Timestamp) -> Result<()> {
+            if self.admin == self.env().caller() {
+                if time == 0 {
+                    return Err(Error::InvalidExpiry);
+                }
+                if self.config_delay == 0 {
+                    self.expiry_time = time;
+                } else {
+                    self.pending_config.expiry_time = Some(time);
+                    self.restart_timelock()?;
+                }
+                Ok(())
+            } else {
+                Err(Error::InvalidCaller)
+            }
+        }
+
+        /// Sets the display ticker, zero padded to 8 bytes
+        #[ink(message)]
+        pub fn set_symbol(&mut self, symbol: [u8; 8]) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            self.symbol = symbol;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_symbol(&self) -> [u8; 8] {
+            self.symbol
+        }
+
+        /// Extends every expiry check by `grace` ms to absorb validator clock skew. Claims stay
+        /// open and refunds start that much later, so the two never overlap.
+        #[ink(message)]
+        pub fn set_clock_skew_grace(&mut self, grace: Timestamp) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if grace > MAX_CLOCK_SKEW_GRACE {
+                return Err(Error::ClockSkewGraceTooLarge);
+            }
+            self.clock_skew_grace = grace;
+            Ok(())
+        }
+
+        /// Same as `set_expiry_period` but takes seconds, e.g. 86_400 for 24 hours
+        #[ink(message)]
+        pub fn set_expiry_seconds(&mut self, seconds: u32) -> Result<()> {
+            let time = Timestamp::from(seconds)
+                .checked_mul(1000)
+                .ok_or(Error::Overflow)?;
+            self.set_expiry_period(time)
+        }
+
+        /// Sets the claim window (the expiry period) and the extra delay before refunds
+        /// together; nothing changes unless both are valid. Applies to new payments only
+        #[ink(message)]
+        pub fn set_windows(&mut self, claim_window: Timestamp, refund_delay: Timestamp) -> Result<()> {
+            let admin = self.env().caller();
+            if self.admin != admin {
+                return Err(Error::InvalidCaller);
+            }
+            if claim_window == 0 {
+                return Err(Error::InvalidExpiry);
+            }
+            if claim_window.checked_add(refund_delay).is_none() {
+                return Err(Error::Overflow);
+            }
+
+            if self.config_delay > 0 {
+                self.pending_config.expiry_time = Some(claim_window);
+                self.pending_config.refund_delay = Some(refund_delay);
+                return self.restart_timelock();
+            }
+
+            self.expiry_time = claim_window;
+            self.refund_delay = refund_delay;
+            self.emit(ConfigChanged {
+                claim_window,
+                refund_delay,
+                admin,
+            });
+            Ok(())
+        }
+
+        /// Sets how long threshold, reference threshold and expiry changes wait before
+        /// `apply_pending_config` may apply them; 0 makes them immediate. A longer delay
+        /// applies at once, while a shorter one is staged under the current delay so it
+        /// cannot be used to skip it
+        #[ink(message)]
+        pub fn set_config_delay(&mut self, delay: Timestamp) -> Result<()> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            if delay >= self.config_delay {
+                self.config_delay = delay;
+                return Ok(());
+            }
+            self.pending_config.config_delay = Some(delay);
+            self.restart_timelock()
+        }
+
+        /// Changes staged under the timelock, with the time they may be applied
+        #[ink(message)]
+        pub fn get_pending_config(&self) -> PendingConfig {
+            self.pending_config.clone()
+        }
+
+        /// Applies all staged changes once their delay has passed; anyone may call it
+        #[ink(message)]
+        pub fn apply_pending_config(&mut self) -> Result<()> {
+            if self.pending_config.is_empty() {
+                return Err(Error::NotAllowed);
+            }
+            if self.env().block_timestamp() < self.pending_config.effective_at {
+                return Err(Error::TimelockNotElapsed);
+            }
+
+            let pending = core::mem::take(&mut self.pending_config);
+            if let Some(threshold_value) = pending.threshold_value {
+                self.threshold_value = threshold_value;
+            }
+            if let Some(expiry_time) = pending.expiry_time {
+                self.expiry_time = expiry_time;
+            }
+            if let Some(refund_delay) = pending.refund_delay {
+                self.refund_delay = refund_delay;
+            }
+            if let Some(config_delay) = pending.config_delay {
+                self.config_delay = config_delay;
+            }
+            if let Some((oracle, threshold_in_reference)) = pending.reference_threshold {
+                self.oracle = oracle;
+                self.threshold_in_reference = threshold_in_reference;
+            }
+            self.emit(ConfigChanged {
+                claim_window: self.expiry_time,
+                refund_delay: self.refund_delay,
+                admin: self.admin,
+            });
+            Ok(())
+        }
+
+        /// Expiry period the contract was deployed with; the live value may differ
+        #[ink(message)]
+        pub fn default_expiry(&self) -> Timestamp {
+            DEFAULT_EXPIRY_TIME
+        }
+
+        /// Threshold the contract was deployed with; the live value may differ
+        #[ink(message)]
+        pub fn default_threshold(&self) -> Balance {
+            DEFAULT_THRESHOLD
+        }
+
+        /// Returns the maximum number of items accepted by batch messages
+        #[ink(message)]
+        pub fn max_batch_size(&self) -> u32 {
+            MAX_BATCH_SIZE
+        }
+
+        // #[ink(message)]
+        // pub fn view_current_time(&self) -> Timestamp {
+        //     let time = self.env().block_timestamp();
+        //     ink::env::debug_println!("{:?}", time);
+        //     time
+        // }
+
+         #[ink(message)]
+        pub fn view_payment_record(&self, payment_id: Hash) -> PaymentInfo {
+            self.payment_records.get(payment_id).unwrap()
+        }
+
+        /// Address of this contract, as used by the self-payment check
+        #[ink(message)]
+        pub fn contract_address(&self) -> AccountId {
+            self.env().account_id()
+        }
+
+        /// Whether `otp` could have been produced by the generator, so wallets can reject
+        /// typos before spending an attempt
+        #[ink(message)]
+        pub fn is_valid_otp_format(&self, otp: u32) -> bool {
+            is_generated_otp(otp)
+        }
+
+        /// Re-derives the id from the stored record, with its lifecycle fields (status,
+        /// attempts, dispute flag) reset to their creation values. `false` means an
+        /// identifying field changed after creation, e.g. the sender via ownership transfer
+        #[ink(message)]
+        pub fn verify_id(&self, payment_id: Hash) -> Result<bool> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            let mut payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+            payment_info.otp_attempts = 1;
+            payment_info.status = PaymentStatus::Waiting;
+            payment_info.last_attempt_time = 0;
+            payment_info.disputed = false;
+            payment_info.last_wrong_otp = None;
+            Ok(self.get_transaction_id(&payment_info) == payment_id)
+        }
+
+        /// Portable voucher for a payment that wallets can display to the receiver
+        #[ink(message)]
+        pub fn export_voucher(&self, payment_id: Hash) -> Result<PaymentVoucher> {
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+            Ok(PaymentVoucher {
+                payment_id,
+                amount: payment_info.amount,
+                receiver: payment_info.receiver,
+                expires_at: self.claim_deadline(&payment_info),
+            })
+        }
+
+        /// Up to the last MAX_PAYMENT_EVENTS events of a payment as `(kind, timestamp)`, oldest
+        /// first. Kinds: 0 requested, 1 status info, 2 acknowledged, 3 paid out,
+        /// 4 ownership transferred, 5 dispute raised, 6 refunded, 7 claim expiring soon.
+        /// The log outlives a purged record
+        #[ink(message)]
+        pub fn payment_events(&self, payment_id: Hash) -> Result<PaymentLog> {
+            self.payment_logs
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)
+        }
+
+        /// Page of payment ids whose amount lies in `min..=max`, in bucket then send order.
+        /// Returns at most `limit` (at most MAX_BATCH_SIZE) ids after visiting at most
+        /// MAX_AMOUNT_SCAN index positions, with the cursor to pass next time, or `None`
+        /// once the range is exhausted; start with `None`. A page may be short or empty
+        /// while a cursor is returned. Purged records no longer have an amount and are left out
+        #[ink(message)]
+        pub fn list_by_amount_range(
+            &self,
+            min: Balance,
+            max: Balance,
+            cursor: Option<AmountCursor>,
+            limit: u32,
+        ) -> Result<(Vec<Hash>, Option<AmountCursor>)> {
+            if min > max {
+                return Err(Error::InvalidRange);
+            }
+            if limit > MAX_BATCH_SIZE {
+                return Err(Error::BatchTooLarge);
+            }
+
+            let first = amount_bucket(min);
+            let (mut bucket, mut position) = match cursor {
+                Some((bucket, position)) if bucket >= first => (bucket, position),
+                _ => (first, 0),
+            };
+            let mut visited = 0;
+            let mut ids = Vec::new();
+            while bucket <= amount_bucket(max) {
+                let len = self.amount_bucket_len.get(bucket).unwrap_or(0);
+                while position < len {
+                    if ids.len() as u32 == limit || visited == MAX_AMOUNT_SCAN {
+                        return Ok((ids, Some((bucket, position))));
+                    }
+                    visited += 1;
+                    let id = self.amount_index.get((bucket, position));
+                    position += 1;
+                    let Some(id) = id else {
+                        continue;
+                    };
+                    let in_range = self
+                        .payment_records
+                        .get(id)
+                        .is_some_and(|payment_info| (min..=max).contains(&payment_info.amount));
+                    if in_range {
+                        ids.push(id);
+                    }
+                }
+                bucket += 1;
+                position = 0;
+            }
+            Ok((ids, None))
+        }
+
+        /// Page of ids of payments to `receiver` whose reference is exactly `reference`, in
+        /// send order; several payments may share one, e.g. instalments of an invoice.
+        /// `start` is a position in that list; `limit` is at most MAX_BATCH_SIZE
+        #[ink(message)]
+        pub fn find_by_reference(
+            &self,
+            receiver: AccountId,
+            reference: Vec<u8>,
+            start: u32,
+            limit: u32,
+        ) -> Result<Vec<Hash>> {
+            if limit > MAX_BATCH_SIZE {
+                return Err(Error::BatchTooLarge);
+            }
+            let key = reference_key(receiver, &reference);
+            let end = self
+                .reference_len
+                .get(key)
+                .unwrap_or(0)
+                .min(start.saturating_add(limit));
+            Ok((start..end)
+                .filter_map(|position| self.reference_index.get((key, position)))
+                .collect())
+        }
+
+        /// Returns `(sender, receiver)` of a payment without its amount or OTP
+        #[ink(message)]
+        pub fn counterparties(&self, payment_id: Hash) -> Result<(AccountId, AccountId)> {
+            self.payment_records
+                .get(payment_id)
+                .map(|payment_info| (payment_info.sender, payment_info.receiver))
+                .ok_or(Error::PaymentRecordMissing)
+        }
+
+        /// Records for a page of ids in input order, `None` for unknown ids; OTPs are blanked
+        #[ink(message)]
+        pub fn get_records(&self, ids: Vec<Hash>) -> Result<Vec<Option<PaymentInfo>>> {
+            if ids.len() > MAX_BATCH_SIZE as usize {
+                return Err(Error::BatchTooLarge);
+            }
+            Ok(ids
+                .into_iter()
+                .map(|id| {
+                    self.payment_records.get(id).map(|mut payment_info| {
+                        payment_info.otp = 0;
+                        payment_info
+                    })
+                })
+                .collect())
+        }
+
+
+
+        fn all_attempts_done(
+            &mut self,
+            payment_info: &mut PaymentInfo,
+            payment_id: Hash,
+        ) -> Result<ClaimOutcome> {
+            // refund payment to sender, or to admin if the sender gave up refunds
+            let refund_to = if payment_info.refundable {
+                payment_info.sender
+            } else {
+                self.admin
+            };
+            self.pay(refund_to, payment_info.amount)?;
+            self.release_escrow(payment_info);
+
+            self.set_status(payment_info, PaymentStatus::AllAttemptsFailed);
+            if payment_info.purge_on_failure {
+                self.payment_records.remove(payment_id);
+            } else {
+                self.payment_records.insert(payment_id, &*payment_info);
+            }
+
+            if payment_info.refundable {
+                self.emit_refund(payment_info, payment_id, RefundReason::Rejected);
+            }
+            self.emit_payment(SecurePaymentInfo {
+                sender: payment_info.sender,
+                receiver: payment_info.receiver,
+                amount: payment_info.amount,
+                payment_id,
+                status: payment_info.status.clone(),
+            });
+            Ok(ClaimOutcome::AttemptsExhausted)
+        }
+
+        // Only wrong OTPs warn: every other early interaction returns an Err,
+        // which reverts the call along with any event it deposited
+        fn warn_if_expiring(
+            &mut self,
+            payment_info: &PaymentInfo,
+            payment_id: Hash,
+            now: Timestamp,
+        ) {
+            let remaining = self.claim_deadline(payment_info).saturating_sub(now);
+            if self.warn_before > 0 && remaining <= self.warn_before {
+                self.emit_payment(ClaimExpiringSoon {
+                    receiver: payment_info.receiver,
+                    payment_id,
+                    remaining,
+                });
+            }
+        }
+
+        fn one_attempt_done(
+            &mut self,
+            payment_info: &mut PaymentInfo,
+            payment_id: Hash,
+        ) -> Result<ClaimOutcome> {
+            // one more attempt done
+            match payment_info.otp_attempts.checked_add(1) {
+                Some(otps) => payment_info.otp_attempts = otps,
+                None => return Err(Error::Overflow),
+            }
+            self.payment_records.insert(payment_id, &*payment_info);
+
+            self.emit_payment(SecurePaymentInfo {
+                sender: payment_info.sender,
+                receiver: payment_info.receiver,
+                amount: payment_info.amount,
+                payment_id,
+                status: payment_info.status.clone(),
+            });
+            Ok(ClaimOutcome::WrongOtp {
+                attempts_left: attempts_left(payment_info),
+            })
+        }
+
+        // Every condition get_refund enforces, shared with can_refund
+        fn check_refund(&self, payment_info: &PaymentInfo) -> Result<()> {
+            if self.env().caller() != payment_info.sender {
+                return Err(Error::InvalidSender);
+            }
+            self.refund_due(payment_info)
+        }
+
+        // The refund conditions that do not depend on who is asking, shared with tick
+        fn refund_due(&self, payment_info: &PaymentInfo) -> Result<()> {
+            if payment_info.disputed {
+                return Err(Error::Disputed);
+            }
+
+            if !payment_info.refundable {
+                return Err(Error::NonRefundable);
+            }
+
+            let refundable_status = matches!(
+                payment_info.status,
+                PaymentStatus::Waiting | PaymentStatus::Acknowledged | PaymentStatus::Expired
+            );
+            if self.is_expired(self.refund_time(payment_info)) && refundable_status {
+                Ok(())
+            } else {
+                Err(Error::NotAllowed)
+            }
+        }
+
+        // Defense in depth: payout plus fee must be exactly the record's amount, and never
+        // more than the contract believes it holds in escrow
+        fn check_payout(&self, payment_info: &PaymentInfo, amount: Balance, fee: Balance) -> Result<()> {
+            match amount.checked_add(fee) {
+                Some(total) if total == payment_info.amount && total <= self.tracked_escrow => Ok(()),
+                _ => Err(Error::BalanceMismatch),
+            }
+        }
+
+        // Any newly staged change pushes the whole pending set back by a full delay
+        fn restart_timelock(&mut self) -> Result<()> {
+            self.pending_config.effective_at = self
+                .env()
+                .block_timestamp()
+                .checked_add(self.config_delay)
+                .ok_or(Error::Overflow)?;
+            Ok(())
+        }
+
+        // Empty references are not indexed
+        fn index_reference(&mut self, receiver: AccountId, reference: &[u8], payment_id: Hash) {
+            if reference.is_empty() {
+                return;
+            }
+            let key = reference_key(receiver, reference);
+            let len = self.reference_len.get(key).unwrap_or(0);
+            self.reference_index.insert((key, len), &payment_id);
+            self.reference_len.insert(key, &len.saturating_add(1));
+        }
+
+        fn index_amount(&mut self, amount: Balance, payment_id: Hash) {
+            let bucket = amount_bucket(amount);
+            let len = self.amount_bucket_len.get(bucket).unwrap_or(0);
+            self.amount_index.insert((bucket, len), &payment_id);
+            self.amount_bucket_len.insert(bucket, &len.saturating_add(1));
+        }
+
+        // Approved agents may claim on the receiver's behalf, but only into the receiver's
+        // account, and never for strict_receiver payments
+        fn check_claimant(
+            &self,
+            payment_info: &PaymentInfo,
+            caller: AccountId,
+            payout: &Payout,
+        ) -> Result<()> {
+            if caller != payment_info.receiver
+                && (payout.redirected() || !self.is_agent(payment_info.receiver, caller))
+            {
+                return Err(Error::InvalidReceiver);
+            }
+            if payment_info.strict_receiver
+                && (caller != payment_info.receiver || !payout.only_to(payment_info.receiver))
+            {
+                return Err(Error::InvalidReceiver);
+            }
+            Ok(())
+        }
+
+        // Refunds an expired payment to its sender once the caller has checked eligibility
+        fn refund_expired(&mut self, payment_id: Hash, payment_info: &mut PaymentInfo) -> Result<()> {
+            self.record_expired_refund(payment_id, payment_info);
+            self.pay(payment_info.sender, payment_info.amount)
+        }
+
+        // Marks an expired payment refunded and announces it; the caller moves the funds
+        fn record_expired_refund(&mut self, payment_id: Hash, payment_info: &mut PaymentInfo) {
+            self.set_status(payment_info, PaymentStatus::Refunded);
+            self.payment_records.insert(payment_id, &*payment_info);
+            self.release_escrow(payment_info);
+
+            self.emit_refund(payment_info, payment_id, RefundReason::ExpiredRefund);
+            self.emit_payment(SecurePaymentInfo {
+                sender: payment_info.sender,
+                receiver: payment_info.receiver,
+                amount: payment_info.amount,
+                payment_id,
+                status: payment_info.status.clone(),
+            });
+        }
+
+        fn push_outstanding(&mut self, payment_id: Hash) {
+            self.outstanding_queue.insert(self.queue_tail, &payment_id);
+            self.queue_tail = self.queue_tail.saturating_add(1);
+        }
+
+        fn pop_outstanding(&mut self) {
+            self.outstanding_queue.remove(self.queue_head);
+            self.queue_head = self.queue_head.saturating_add(1);
+        }
+
+        // Admin-only lookup of a payment that is disputed and still unsettled
+        fn disputed_payment(&self, payment_id: Hash) -> Result<PaymentInfo> {
+            if self.admin != self.env().caller() {
+                return Err(Error::InvalidCaller);
+            }
+            let payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+            let unsettled = matches!(
+                payment_info.status,
+                PaymentStatus::Waiting | PaymentStatus::Acknowledged | PaymentStatus::Expired
+            );
+            if !payment_info.disputed || !unsettled {
+                return Err(Error::NotAllowed);
+            }
+            Ok(payment_info)
+        }
+
+        fn cancel_one(&mut self, payment_id: Hash) -> Result<()> {
+            let mut payment_info = self
+                .payment_records
+                .get(payment_id)
+                .ok_or(Error::PaymentRecordMissing)?;
+
+            if self.env().caller() != payment_info.sender {
+                return Err(Error::InvalidSender);
+            }
+
+            if payment_info.disputed {
+                return Err(Error::Disputed);
+            }
+
+            if !payment_info.refundable {
+                return Err(Error::NonRefundable);
+            }
+
+            if payment_info.status != PaymentStatus::Waiting
+                && payment_info.status != PaymentStatus::Acknowledged
+            {
+                return Err(Error::NotAllowed);
+            }
+
+            // cancelling would hand the funds back before the receiver's owed attempts
+            if awaiting_attempts(&payment_info) {
+                return Err(Error::NotAllowed);
+            }
+
+            // everything that can fail short of a transfer is settled before any write, so
+            // the only error left after the record changes is TransferFailed
+            let fee = self.fee_for(payment_info.amount, self.cancel_fee_bps)?;
+            let refund = payment_info.amount.checked_sub(fee).ok_or(Error::Overflow)?;
+            let cancel_fees_collected =
+                self.cancel_fees_collected.checked_add(fee).ok_or(Error::Overflow)?;
+
+            self.set_status(&mut payment_info, PaymentStatus::Refunded);
+            self.payment_records.insert(payment_id, &payment_info);
+            self.release_escrow(&payment_info);
+
+            self.pay(payment_info.sender, refund)?;
+            if fee > 0 {
+                self.pay(self.fee_recipient, fee)?;
+            }
+            self.cancel_fees_collected = cancel_fees_collected;
+
+            self.emit_refund(&payment_info, payment_id, RefundReason::CancelledBySender);
+            self.emit_payment(SecurePaymentInfo {
+                sender: payment_info.sender,
+                receiver: payment_info.receiver,
+                amount: payment_info.amount,
+                payment_id,
+                status: payment_info.status,
+            });
+            Ok(())
+        }
+
+        fn pay(&self, to: AccountId, amount: Balance) -> Result<()> {
+            self.env()
+                .transfer(to, amount)
+                .map_err(|_| Error::TransferFailed)
+        }
+
+        fn emit_refund(&mut self, payment_info: &PaymentInfo, payment_id: Hash, reason: RefundReason) {
+            self.emit_payment(PaymentRefunded {
+                sender: payment_info.sender,
+                receiver: payment_info.receiver,
+                amount: payment_info.amount,
+                payment_id,
+                reason,
+            });
+        }
+
+        // Appends to the payment's log, evicting the oldest entry once it holds
+        // MAX_PAYMENT_EVENTS, then emits as usual
+        fn emit_payment<E: PaymentEvent>(&mut self, event: E) {
+            let payment_id = event.payment_id();
+            let mut log = self.payment_logs.get(payment_id).unwrap_or_default();
+            if log.len() >= MAX_PAYMENT_EVENTS {
+                log.remove(0);
+            }
+            log.push((E::KIND, self.env().block_timestamp()));
+            self.payment_logs.insert(payment_id, &log);
+            self.emit(event);
+        }
+
+        fn emit<E: ink::env::Event>(&self, event: E) {
+            #[cfg(feature = "std")]
+            event_counter::bump(core::any::type_name::<E>());
+            self.env().emit_event(event);
+        }
+
+        /// Number of `E` events emitted so far on this thread (off-chain diagnostics only)
+        #[cfg(feature = "std")]
+        pub fn emitted_event_count<E: ink::env::Event>(&self) -> u32 {
+            event_counter::get(core::any::type_name::<E>())
+        }
+
+        /// Panics if storage is inconsistent for the given set of payment ids (off-chain tests only).
+        ///
+        /// `ids` must list every payment created so far, since the mapping cannot be iterated.
+        /// Records purged after exhausting their attempts are absent, so that counter is only
+        /// bounded from below.
+        #[cfg(feature = "std")]
+        pub fn check_invariants(&self, ids: &[Hash]) {
+            let mut counts = StatusCounts::default();
+            let mut escrowed: Balance = 0;
+
+            for payment_info in ids.iter().filter_map(|id| self.payment_records.get(id)) {
+                counts.increment(&payment_info.status);
+                if matches!(
+                    payment_info.status,
+                    PaymentStatus::Waiting | PaymentStatus::Acknowledged | PaymentStatus::Expired
+                ) {
+                    escrowed += payment_info.amount;
+                }
+            }
+
+            assert_eq!(self.tracked_escrow, escrowed, "tracked_escrow drifted");
+            assert!(self.tracked_escrow <= self.env().balance(), "escrow exceeds balance");
+
+            let stored = &self.status_counts;
+            assert_eq!(stored.waiting, counts.waiting, "waiting count");
+            assert_eq!(stored.success, counts.success, "success count");
+            assert_eq!(stored.refunded, counts.refunded, "refunded count");
+            assert_eq!(stored.expired, counts.expired, "expired count");
+            assert_eq!(stored.forfeited, counts.forfeited, "forfeited count");
+            assert_eq!(stored.replaced, counts.replaced, "replaced count");
+            assert!(
+                stored.all_attempts_failed >= counts.all_attempts_failed,
+                "all_attempts_failed count"
+            );
+        }
+
+        fn next_attempt_time(&self, payment_info: &PaymentInfo) -> Timestamp {
+            if payment_info.last_attempt_time == 0 {
+                return 0;
+            }
+            payment_info
+                .last_attempt_time
+                .saturating_add(self.min_attempt_gap.max(1))
+        }
+
+        fn adjust_pending(&mut self, receiver: AccountId, up: bool) {
+            let pending = self.receiver_pending.get(receiver).unwrap_or(0);
+            let pending = if up {
+                pending.saturating_add(1)
+            } else {
+                pending.saturating_sub(1)
+            };
+            self.receiver_pending.insert(receiver, &pending);
+        }
+
+        // Moves a payment to a new status, keeping the status and per-receiver counters in step
+        fn set_status(&mut self, payment_info: &mut PaymentInfo, status: PaymentStatus) {
+            self.status_counts.decrement(&payment_info.status);
+            self.status_counts.increment(&status);
+            let was_pending = is_pending(&payment_info.status);
+            if was_pending != is_pending(&status) {
+                self.adjust_pending(payment_info.receiver, !was_pending);
+                self.active_escrow = if was_pending {
+                    self.active_escrow.saturating_sub(payment_info.amount)
+                } else {
+                    self.active_escrow.saturating_add(payment_info.amount)
+                };
+            }
+            if status == PaymentStatus::Success {
+                let successes = self.receiver_success_count(payment_info.receiver);
+                self.receiver_successes
+                    .insert(payment_info.receiver, &successes.saturating_add(1));
+            }
+            payment_info.status = status;
+        }
+
+        // Best effort: a failing or misbehaving notifier never blocks the payment
+        fn notify_receiver(
+            &self,
+            receiver: AccountId,
+            payment_id: Hash,
+            sender: AccountId,
+            amount: Balance,
+            callback_data: &[u8],
+        ) {
+            let Some(notifier) = self.notifiers.get(receiver) else {
+                return;
+            };
+            let _ = build_call::<DefaultEnvironment>()
+                .call(notifier)
+                .ref_time_limit(NOTIFIER_REF_TIME_LIMIT)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!(
+                        "on_payment_received"
+                    )))
+                    .push_arg(payment_id)
+                    .push_arg(sender)
+                    .push_arg(amount)
+                    .push_arg(callback_data),
+                )
+                .returns::<()>()
+                .try_invoke();
+        }
+
+        // Fails closed: any oracle failure rejects rather than falling back to a stale value
+        fn global_threshold(&self) -> Result<Balance> {
+            let Some(oracle) = self.oracle else {
+                return Ok(self.threshold_value);
+            };
+            build_call::<DefaultEnvironment>()
+                .call(oracle)
+                .ref_time_limit(NOTIFIER_REF_TIME_LIMIT)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("to_native")))
+                        .push_arg(self.threshold_in_reference),
+                )
+                .returns::<Balance>()
+                .try_invoke()
+                .ok()
+                .and_then(|result| result.ok())
+                .ok_or(Error::OracleUnavailable)
+        }
+
+        // Fails open: only an explicit `false` from the validator rejects the payment
+        fn receiver_accepts(&self, receiver: AccountId, sender: AccountId, amount: Balance) -> bool {
+            let Some(validator) = self.validators.get(receiver) else {
+                return true;
+            };
+            let answer = build_call::<DefaultEnvironment>()
+                .call(validator)
+                .ref_time_limit(NOTIFIER_REF_TIME_LIMIT)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("accept_payment")))
+                        .push_arg(sender)
+                        .push_arg(amount),
+                )
+                .returns::<bool>()
+                .try_invoke();
+            !matches!(answer, Ok(Ok(false)))
+        }
+
+        fn is_agent(&self, receiver: AccountId, account: AccountId) -> bool {
+            self.agents
+                .get(receiver)
+                .is_some_and(|agents| agents.contains(&account))
+        }
+
+        fn ensure_not_zero(&self, account: AccountId) -> Result<()> {
+            if !self.allow_zero_address && account == zero_address() {
+                return Err(Error::ZeroAddress);
+            }
+            Ok(())
+        }
+
+        fn release_escrow(&mut self, payment_info: &PaymentInfo) {
+            self.tracked_escrow = self.tracked_escrow.saturating_sub(payment_info.amount);
+            let locked = self.sender_locked.get(payment_info.sender).unwrap_or(0);
+            self.sender_locked.insert(
+                payment_info.sender,
+                &locked.saturating_sub(payment_info.amount),
+            );
+        }
+
+        fn is_confirmed(&self, payment_info: &PaymentInfo) -> bool {
+            self.env()
+                .block_number()
+                .saturating_sub(payment_info.recorded_block)
+                >= self.min_confirmations
+        }
+
+        // A receiver that has not yet made min_attempts_before_refund attempts gets one more
+        // claim window; computed rather than stored, so it can only ever apply once
+        fn deadline_extension(&self, payment_info: &PaymentInfo) -> Timestamp {
+            if awaiting_attempts(payment_info) {
+                payment_info.expires_at.saturating_sub(payment_info.recorded_time)
+            } else {
+                0
+            }
+        }
+
+        fn claim_deadline(&self, payment_info: &PaymentInfo) -> Timestamp {
+            payment_info
+                .expires_at
+                .saturating_add(self.deadline_extension(payment_info))
+        }
+
+        fn refund_time(&self, payment_info: &PaymentInfo) -> Timestamp {
+            payment_info
+                .refundable_at
+                .saturating_add(self.deadline_extension(payment_info))
+        }
+
+        // The grace is subtracted from the clock with saturation, so a clock behind
+        // recorded_time still reads as not expired.
+        // Deadlines are inclusive: at exactly `expires_at` a payment is still claimable and
+        // only strictly later is it expired. Claims and refunds both go through this check,
+        // and refundable_at >= expires_at, so at no instant can both succeed
+        fn is_expired(&self, expires_at: Timestamp) -> bool {
+            block_timestamp::<DefaultEnvironment>().saturating_sub(self.clock_skew_grace) > expires_at
+        }
+        
+        /// Returns the payment_id of payment_info
+        fn get_transaction_id(&self, payment_info: &PaymentInfo) -> Hash {
+            let encodable = payment_info;
+            let mut payment_id = <Sha2x256 as HashOutput>::Type::default();
+            ink::env::hash_encoded::<Sha2x256, _>(&encodable, &mut payment_id);
+            Hash::from(payment_id)
+        }
+
+        // Charges nothing rather than burn the fee if the recipient is somehow the zero address
+        fn fee_for(&self, amount: Balance, fee_bps: u16) -> Result<Balance> {
+            if self.fee_recipient == zero_address() {
+                return Ok(0);
+            }
+            mul_div(amount, fee_bps as Balance, BPS_DENOMINATOR, self.rounding)
+        }
+
+        // Splits the escrowed amount into (payout, fee); the two always add up to the amount
+        fn get_amount(&self, payment_info: &PaymentInfo) -> Result<(Balance, Balance)> {
+            let fee = self.fee_for(payment_info.amount, self.fee_bps)?;
+            let payout = payment_info.amount.checked_sub(fee).ok_or(Error::Overflow)?;
+            Ok((payout, fee))
+        }
+    }
+      
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink::env::test;
+
+        type Env = DefaultEnvironment;
+
+        // Every default account starts with enough to send many threshold-sized payments
+        const FUNDS: Balance = 1_000_000 * DEFAULT_THRESHOLD;
+        const AMOUNT: Balance = DEFAULT_THRESHOLD;
+        const START: Timestamp = 1_000_000;
+
+        fn accounts() -> test::DefaultAccounts<Env> {
+            test::default_accounts::<Env>()
+        }
+
+        fn contract_id() -> AccountId {
+            AccountId::from([0xC0; 32])
+        }
+
+        fn balance_of(account: AccountId) -> Balance {
+            test::get_account_balance::<Env>(account).unwrap_or(0)
+        }
+
+        fn set_caller(caller: AccountId) {
+            test::set_caller::<Env>(caller);
+        }
+
+        fn now() -> Timestamp {
+            ink::env::block_timestamp::<Env>()
+        }
+
+        // Moves the clock forward without starting a new block
+        fn advance_time(by: Timestamp) {
+            test::set_block_timestamp::<Env>(now() + by);
+        }
+
+        // Deploys with alice as admin and funds the default accounts
+        fn setup() -> PaymentContract {
+            let accounts = accounts();
+            for account in [accounts.alice, accounts.bob, accounts.charlie, accounts.django] {
+                test::set_account_balance::<Env>(account, FUNDS);
+            }
+            test::set_account_balance::<Env>(contract_id(), 0);
+            test::set_callee::<Env>(contract_id());
+            test::set_block_timestamp::<Env>(START);
+            set_caller(accounts.alice);
+            PaymentContract::new(accounts.alice)
+        }
+
+        // Sends as `sender` with exactly `amount` attached; the value is cleared afterwards
+        // so it does not leak into the next call
+        fn send_with(
+            contract: &mut PaymentContract,
+            sender: AccountId,
+            receiver: AccountId,
+            amount: Balance,
+            options: PaymentOptions,
+        ) -> Result<Hash> {
+            set_caller(sender);
+            test::transfer_in::<Env>(amount);
+            let sent = contract.send_payment_with_options(receiver, amount, options);
+            test::set_value_transferred::<Env>(0);
+            sent
+        }
+
+        fn send_amount(
+            contract: &mut PaymentContract,
+            sender: AccountId,
+            receiver: AccountId,
+            amount: Balance,
+        ) -> Result<Hash> {
+            send_with(contract, sender, receiver, amount, PaymentOptions::default())
+        }
+
+        // Alice sends `amount` to `receiver` with `funded` attached
+        fn send_funded(
+            contract: &mut PaymentContract,
+            receiver: AccountId,
+            amount: Balance,
+            funded: Balance,
+        ) -> Result<()> {
+            set_caller(accounts().alice);
+            test::transfer_in::<Env>(funded);
+            let sent = contract.send_payment(receiver, amount);
+            test::set_value_transferred::<Env>(0);
+            sent
+        }
+
+        fn send(contract: &mut PaymentContract, sender: AccountId, receiver: AccountId) -> Hash {
+            send_with(contract, sender, receiver, AMOUNT, PaymentOptions::default())
+                .expect("send failed")
+        }
+
+        // Events of type `E` recorded so far, told apart by their signature topic
+        fn emitted<E: ink::env::Event>() -> usize {
+            let signature = E::SIGNATURE_TOPIC.expect("event is anonymous");
+            test::recorded_events()
+                .filter(|event| event.topics.first().is_some_and(|topic| topic[..] == signature))
+                .count()
+        }
+
+        // Payloads of the `E` events recorded so far, oldest first
+        fn decoded<E: ink::env::Event + scale::Decode>() -> Vec<E> {
+            let signature = E::SIGNATURE_TOPIC.expect("event is anonymous");
+            test::recorded_events()
+                .filter(|event| event.topics.first().is_some_and(|topic| topic[..] == signature))
+                .map(|event| E::decode(&mut &event.data[..]).expect("bad event"))
+                .collect()
+        }
+
+        fn record(contract: &PaymentContract, payment_id: Hash) -> PaymentInfo {
+            contract
+                .payment_records
+                .get(payment_id)
+                .expect("payment record missing")
+        }
+
+        fn otp_of(contract: &PaymentContract, payment_id: Hash) -> u32 {
+            record(contract, payment_id).otp
+        }
+
+        fn wrong_otp(contract: &PaymentContract, payment_id: Hash) -> u32 {
+            let otp = otp_of(contract, payment_id);
+            if otp == OTP_MAX { OTP_MIN } else { otp + 1 }
+        }
+
+        // Two distinct OTPs that are both wrong for the payment
+        fn wrong_otps(contract: &PaymentContract, payment_id: Hash) -> (u32, u32) {
+            let otp = otp_of(contract, payment_id);
+            let mut wrong = (OTP_MIN..).filter(|candidate| *candidate != otp);
+            (wrong.next().unwrap(), wrong.next().unwrap())
+        }
+
+        // Submits `otp` as the receiver, spaced past the one-attempt-per-instant rule
+        fn submit(
+            contract: &mut PaymentContract,
+            payment_id: Hash,
+            otp: u32,
+        ) -> Result<ClaimOutcome> {
+            let payment_info = record(contract, payment_id);
+            advance_time(1);
+            set_caller(payment_info.receiver);
+            contract.receive_payment(payment_id, otp)
+        }
+
+        fn submit_wrong(contract: &mut PaymentContract, payment_id: Hash) -> Result<ClaimOutcome> {
+            let otp = wrong_otp(contract, payment_id);
+            submit(contract, payment_id, otp)
+        }
+
+        #[ink::test]
+        fn max_batch_size_reports_the_cap() {
+            let contract = setup();
+            assert_eq!(contract.max_batch_size(), MAX_BATCH_SIZE);
+        }
+
+        #[ink::test]
+        fn batch_messages_reject_more_than_max_batch_size() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let payment_id = send(&mut contract, accounts.alice, accounts.bob);
+            let too_many = contract.max_batch_size() + 1;
+
+            set_caller(accounts.alice);
+            assert_eq!(
+                contract.cancel_payment_batch(vec![payment_id; too_many as usize]),
+                Err(Error::BatchTooLarge)
+            );
+            assert_eq!(
+                contract.get_records(vec![payment_id; too_many as usize]).err(),
+                Some(Error::BatchTooLarge)
+            );
+            assert_eq!(
+                contract.get_receiver_thresholds(vec![accounts.bob; too_many as usize]),
+                Err(Error::BatchTooLarge)
+            );
+            assert_eq!(contract.tick(too_many), Err(Error::BatchTooLarge));
+            assert_eq!(
+                contract.list_by_amount_range(0, Balance::MAX, None, too_many),
+                Err(Error::BatchTooLarge)
+            );
+
+            set_caller(accounts.bob);
+            let otp = otp_of(&contract, payment_id);
+            let splits = vec![(accounts.bob, 1); too_many as usize];
+            assert_eq!(
+                contract.receive_split(payment_id, otp, splits),
+                Err(Error::BatchTooLarge)
+            );
+            assert_eq!(record(&contract, payment_id).status, PaymentStatus::Waiting);
+        }
+
+        #[ink::test]
+        fn batch_messages_accept_exactly_max_batch_size() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let payment_id = send(&mut contract, accounts.alice, accounts.bob);
+            let ids = vec![payment_id; MAX_BATCH_SIZE as usize];
+
+            let records = contract.get_records(ids.clone()).expect("page rejected");
+            assert_eq!(records.len(), MAX_BATCH_SIZE as usize);
+
+            set_caller(accounts.alice);
+            let results = contract.cancel_payment_batch(ids).expect("batch rejected");
+            assert_eq!(results.len(), MAX_BATCH_SIZE as usize);
+            assert_eq!(results[0], Ok(()));
+            assert!(results[1..].iter().all(|result| *result == Err(Error::NotAllowed)));
+        }
+
+        #[ink::test]
+        fn wrong_otp_is_recorded_and_retries_must_wait_for_the_gap() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_min_attempt_gap(60_000).expect("admin call failed");
+            let payment_id = send(&mut contract, accounts.alice, accounts.bob);
+
+            assert_eq!(
+                submit_wrong(&mut contract, payment_id),
+                Ok(ClaimOutcome::WrongOtp { attempts_left: 2 })
+            );
+            let attempted_at = now();
+            let payment_info = record(&contract, payment_id);
+            assert_eq!(payment_info.otp_attempts, 2);
+            assert_eq!(payment_info.last_attempt_time, attempted_at);
+            assert_eq!(contract.next_attempt_allowed_at(payment_id), Ok(attempted_at + 60_000));
+
+            let otp = otp_of(&contract, payment_id);
+            assert_eq!(contract.receive_payment(payment_id, otp), Err(Error::AttemptTooSoon));
+            advance_time(59_999);
+            assert_eq!(contract.receive_payment(payment_id, otp), Err(Error::AttemptTooSoon));
+
+            advance_time(1);
+            assert_eq!(contract.next_attempt_allowed_at(payment_id), Ok(now()));
+            let before = balance_of(accounts.bob);
+            assert_eq!(contract.receive_payment(payment_id, otp), Ok(ClaimOutcome::Paid));
+            assert_eq!(balance_of(accounts.bob), before + AMOUNT);
+            assert_eq!(record(&contract, payment_id).status, PaymentStatus::Success);
+        }
+
+        #[ink::test]
+        fn retry_at_the_same_timestamp_is_rejected_without_a_gap() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let payment_id = send(&mut contract, accounts.alice, accounts.bob);
+
+            submit_wrong(&mut contract, payment_id).expect("claim failed");
+            let otp = wrong_otp(&contract, payment_id);
+            assert_eq!(contract.receive_payment(payment_id, otp), Err(Error::AttemptTooSoon));
+            assert_eq!(record(&contract, payment_id).otp_attempts, 2);
+        }
+
+        #[ink::test]
+        fn claim_after_the_deadline_marks_the_payment_expired() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let payment_id = send(&mut contract, accounts.alice, accounts.bob);
+
+            advance_time(DEFAULT_EXPIRY_TIME + 1);
+            set_caller(accounts.bob);
+            let otp = otp_of(&contract, payment_id);
+            assert_eq!(contract.receive_payment(payment_id, otp), Ok(ClaimOutcome::Expired));
+            assert_eq!(record(&contract, payment_id).status, PaymentStatus::Expired);
+            assert_eq!(
+                contract.receive_payment(payment_id, otp),
+                Err(Error::AlreadyReceivedPayment)
+            );
+        }
+
+        #[ink::test]
+        fn total_attempts_counts_right_and_wrong_otps() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let first = send(&mut contract, accounts.alice, accounts.bob);
+            let second = send(&mut contract, accounts.alice, accounts.charlie);
+
+            submit_wrong(&mut contract, first).expect("claim failed");
+            submit_wrong(&mut contract, first).expect("claim failed");
+            submit_wrong(&mut contract, second).expect("claim failed");
+            assert_eq!(contract.get_total_attempts(), 3);
+
+            advance_time(1);
+            set_caller(accounts.bob);
+            let otp = otp_of(&contract, first);
+            assert_eq!(contract.receive_payment(first, otp), Ok(ClaimOutcome::Paid));
+            assert_eq!(contract.get_total_attempts(), 4);
+
+            // refused before the OTP is compared
+            assert_eq!(
+                contract.receive_payment(first, otp),
+                Err(Error::AlreadyReceivedPayment)
+            );
+            set_caller(accounts.django);
+            let otp = otp_of(&contract, second);
+            assert_eq!(contract.receive_payment(second, otp), Err(Error::InvalidReceiver));
+            assert_eq!(contract.get_total_attempts(), 4);
+            assert_eq!(contract.dashboard().total_otp_attempts, 4);
+        }
+
+        #[ink::test]
+        fn repeated_wrong_otp_is_free_only_under_lenient_repeats() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let strict = send(&mut contract, accounts.alice, accounts.bob);
+            let (first_wrong, _) = wrong_otps(&contract, strict);
+            submit(&mut contract, strict, first_wrong).expect("claim failed");
+            submit(&mut contract, strict, first_wrong).expect("claim failed");
+            assert_eq!(record(&contract, strict).otp_attempts, 3);
+
+            set_caller(accounts.alice);
+            contract.set_lenient_repeats(true).expect("admin call failed");
+            let lenient = send(&mut contract, accounts.alice, accounts.bob);
+            let (first_wrong, second_wrong) = wrong_otps(&contract, lenient);
+            let wrong_once = Ok(ClaimOutcome::WrongOtp { attempts_left: 2 });
+            assert_eq!(submit(&mut contract, lenient, first_wrong), wrong_once);
+            assert_eq!(submit(&mut contract, lenient, first_wrong), wrong_once);
+            assert_eq!(submit(&mut contract, lenient, first_wrong), wrong_once);
+            let payment_info = record(&contract, lenient);
+            assert_eq!(payment_info.otp_attempts, 2);
+            assert_eq!(
+                payment_info.last_wrong_otp,
+                Some(otp_fingerprint(lenient, first_wrong))
+            );
+
+            // a different wrong OTP still costs an attempt
+            assert_eq!(
+                submit(&mut contract, lenient, second_wrong),
+                Ok(ClaimOutcome::WrongOtp { attempts_left: 1 })
+            );
+            assert_eq!(record(&contract, lenient).otp_attempts, 3);
+        }
+
+        #[ink::test]
+        fn previews_forgive_a_repeated_last_wrong_otp_like_the_claim() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_lenient_repeats(true).expect("admin call failed");
+            let options = PaymentOptions {
+                attempts: Some(2),
+                ..PaymentOptions::default()
+            };
+            let payment_id = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+            let (first_wrong, second_wrong) = wrong_otps(&contract, payment_id);
+            submit(&mut contract, payment_id, first_wrong).expect("claim failed");
+
+            advance_time(1);
+            assert_eq!(
+                contract.preview_receive_error(payment_id, first_wrong),
+                Some(Error::WrongOTP)
+            );
+            assert_eq!(
+                contract.simulate_receive(payment_id, first_wrong),
+                Ok(PaymentStatus::Waiting)
+            );
+            assert_eq!(
+                contract.preview_receive_error(payment_id, second_wrong),
+                Some(Error::AttemptsExceedLimit)
+            );
+            assert_eq!(
+                contract.simulate_receive(payment_id, second_wrong),
+                Ok(PaymentStatus::AllAttemptsFailed)
+            );
+
+            assert_eq!(
+                contract.receive_payment(payment_id, first_wrong),
+                Ok(ClaimOutcome::WrongOtp { attempts_left: 1 })
+            );
+            assert_eq!(
+                submit(&mut contract, payment_id, second_wrong),
+                Ok(ClaimOutcome::AttemptsExhausted)
+            );
+        }
+
+        #[ink::test]
+        fn exhausted_payment_is_purged_or_kept_as_the_sender_chose() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let one_attempt = |purge_on_failure| PaymentOptions {
+                attempts: Some(1),
+                purge_on_failure,
+                ..PaymentOptions::default()
+            };
+            let purged =
+                send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, one_attempt(true))
+                    .expect("send failed");
+            let kept =
+                send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, one_attempt(false))
+                    .expect("send failed");
+
+            let before = balance_of(accounts.alice);
+            assert_eq!(submit_wrong(&mut contract, purged), Ok(ClaimOutcome::AttemptsExhausted));
+            assert_eq!(submit_wrong(&mut contract, kept), Ok(ClaimOutcome::AttemptsExhausted));
+            assert_eq!(balance_of(accounts.alice), before + 2 * AMOUNT);
+
+            assert!(contract.payment_records.get(purged).is_none());
+            assert_eq!(record(&contract, kept).status, PaymentStatus::AllAttemptsFailed);
+            let (waiting, _, _, _, all_attempts_failed) = contract.status_counts();
+            assert_eq!((waiting, all_attempts_failed), (0, 2));
+            assert_eq!(contract.get_tracked_escrow(), 0);
+            assert_eq!(emitted::<PaymentRefunded>(), 2);
+            contract.check_invariants(&[purged, kept]);
+        }
+
+        #[ink::test]
+        fn exhausted_non_refundable_payment_goes_to_the_admin() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let options = PaymentOptions {
+                attempts: Some(1),
+                refundable: false,
+                purge_on_failure: false,
+                ..PaymentOptions::default()
+            };
+            let payment_id =
+                send_with(&mut contract, accounts.charlie, accounts.bob, AMOUNT, options)
+                    .expect("send failed");
+
+            let sender_before = balance_of(accounts.charlie);
+            let admin_before = balance_of(accounts.alice);
+            assert_eq!(
+                submit_wrong(&mut contract, payment_id),
+                Ok(ClaimOutcome::AttemptsExhausted)
+            );
+            assert_eq!(balance_of(accounts.charlie), sender_before);
+            assert_eq!(balance_of(accounts.alice), admin_before + AMOUNT);
+            assert_eq!(record(&contract, payment_id).status, PaymentStatus::AllAttemptsFailed);
+            assert_eq!(emitted::<PaymentRefunded>(), 0);
+        }
+
+        #[ink::test]
+        fn refund_waits_one_more_window_until_the_receiver_has_tried() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_min_attempts_before_refund(1).expect("admin call failed");
+            let untried = send(&mut contract, accounts.alice, accounts.bob);
+            let tried = send(&mut contract, accounts.alice, accounts.bob);
+            submit_wrong(&mut contract, tried).expect("claim failed");
+
+            advance_time(DEFAULT_EXPIRY_TIME);
+            set_caller(accounts.alice);
+            assert_eq!(contract.can_refund(tried), Ok(true));
+            assert_eq!(contract.get_refund(untried), Err(Error::NotAllowed));
+            let before = balance_of(accounts.alice);
+            assert_eq!(contract.get_refund(tried), Ok(()));
+            assert_eq!(balance_of(accounts.alice), before + AMOUNT);
+
+            // the untried payment is still claimable in its extra window
+            assert_eq!(
+                submit_wrong(&mut contract, untried),
+                Ok(ClaimOutcome::WrongOtp { attempts_left: 2 })
+            );
+            set_caller(accounts.alice);
+            assert_eq!(contract.get_refund(untried), Ok(()));
+            assert_eq!(record(&contract, untried).status, PaymentStatus::Refunded);
+        }
+
+        #[ink::test]
+        fn extension_applies_only_once_when_the_receiver_never_tries() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_min_attempts_before_refund(1).expect("admin call failed");
+            let payment_id = send(&mut contract, accounts.alice, accounts.bob);
+
+            advance_time(2 * DEFAULT_EXPIRY_TIME);
+            assert_eq!(contract.can_refund(payment_id), Ok(false));
+            advance_time(1);
+            assert_eq!(contract.can_refund(payment_id), Ok(true));
+            assert_eq!(contract.get_refund(payment_id), Ok(()));
+        }
+
+        #[ink::test]
+        fn cancel_batch_settles_each_cancellable_payment_and_reports_the_rest() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_fee(0, accounts.eve).expect("admin call failed");
+            contract.set_cancel_fee(100).expect("admin call failed");
+            let first = send(&mut contract, accounts.alice, accounts.bob);
+            let foreign = send(&mut contract, accounts.charlie, accounts.bob);
+            let second = send(&mut contract, accounts.alice, accounts.django);
+
+            set_caller(accounts.alice);
+            let before = balance_of(accounts.alice);
+            let results = contract
+                .cancel_payment_batch(vec![first, foreign, second])
+                .expect("batch failed");
+            assert_eq!(results, vec![Ok(()), Err(Error::InvalidSender), Ok(())]);
+
+            let fee = AMOUNT / 100;
+            assert_eq!(balance_of(accounts.alice), before + 2 * (AMOUNT - fee));
+            assert_eq!(balance_of(accounts.eve), 2 * fee);
+            assert_eq!(contract.get_fee_breakdown(), (0, 2 * fee));
+            assert_eq!(record(&contract, first).status, PaymentStatus::Refunded);
+            assert_eq!(record(&contract, foreign).status, PaymentStatus::Waiting);
+            assert_eq!(record(&contract, second).status, PaymentStatus::Refunded);
+            assert_eq!(emitted::<PaymentRefunded>(), 2);
+            contract.check_invariants(&[first, foreign, second]);
+        }
+
+        #[ink::test]
+        fn shorter_config_delay_waits_out_the_current_one() {
+            let mut contract = setup();
+            let delay = 1_000;
+            assert_eq!(contract.set_config_delay(delay), Ok(()));
+            assert_eq!(contract.config_delay, delay);
+
+            assert_eq!(contract.set_config_delay(0), Ok(()));
+            assert_eq!(contract.config_delay, delay);
+            assert_eq!(contract.get_pending_config().config_delay, Some(0));
+            // other changes still wait while the reduction is pending
+            assert_eq!(contract.set_expiry_period(5_000), Ok(()));
+            assert_eq!(contract.expiry_time, DEFAULT_EXPIRY_TIME);
+
+            advance_time(delay - 1);
+            assert_eq!(contract.apply_pending_config(), Err(Error::TimelockNotElapsed));
+            advance_time(1);
+            assert_eq!(contract.apply_pending_config(), Ok(()));
+            assert_eq!(contract.config_delay, 0);
+            assert_eq!(contract.expiry_time, 5_000);
+            assert_eq!(contract.get_pending_config(), PendingConfig::default());
+
+            assert_eq!(contract.set_expiry_period(6_000), Ok(()));
+            assert_eq!(contract.expiry_time, 6_000);
+        }
+
+        #[ink::test]
+        fn tick_refunds_due_payments_queued_behind_one_that_is_not_due() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let slow = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            contract.set_expiry_period(1_000).expect("admin call failed");
+            let fast = send(&mut contract, accounts.charlie, accounts.bob);
+
+            advance_time(1_001);
+            let before = balance_of(accounts.charlie);
+            assert_eq!(contract.tick(2), Ok(2));
+            assert_eq!(record(&contract, slow).status, PaymentStatus::Waiting);
+            assert_eq!(record(&contract, fast).status, PaymentStatus::Refunded);
+            assert_eq!(balance_of(accounts.charlie), before + AMOUNT);
+
+            // the payment that was not due went to the back and is refunded later
+            advance_time(DEFAULT_EXPIRY_TIME);
+            assert_eq!(contract.tick(2), Ok(1));
+            assert_eq!(record(&contract, slow).status, PaymentStatus::Refunded);
+            assert_eq!(emitted::<PaymentRefunded>(), 2);
+            contract.check_invariants(&[slow, fast]);
+        }
+
+        #[ink::test]
+        fn reissue_marks_the_old_payment_replaced_not_refunded() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let old = send(&mut contract, accounts.alice, accounts.bob);
+
+            set_caller(accounts.alice);
+            let before = balance_of(accounts.alice);
+            let new = contract.reissue_payment(old).expect("reissue failed");
+            assert_eq!(balance_of(accounts.alice), before);
+            assert_eq!(record(&contract, old).status, PaymentStatus::Replaced);
+            assert_eq!(record(&contract, new).status, PaymentStatus::Waiting);
+            assert_eq!(emitted::<PaymentRefunded>(), 0);
+            // a leaked code is worthless: it is not the new OTP and the old payment is closed
+            let old_otp = otp_of(&contract, old);
+            assert_ne!(otp_of(&contract, new), old_otp);
+            assert_eq!(submit(&mut contract, old, old_otp), Err(Error::AlreadyReceivedPayment));
+
+            let dashboard = contract.dashboard();
+            assert_eq!((dashboard.refunded, dashboard.replaced), (0, 1));
+            assert_eq!(dashboard.outstanding, AMOUNT);
+            contract.check_invariants(&[old, new]);
+        }
+
+        #[ink::test]
+        fn reissue_applies_the_send_checks_in_force_now() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let payment_id = send(&mut contract, accounts.alice, accounts.bob);
+
+            set_caller(accounts.bob);
+            contract.set_my_receive_cap(Some(AMOUNT - 1)).expect("cap failed");
+            set_caller(accounts.alice);
+            assert_eq!(contract.reissue_payment(payment_id), Err(Error::ExceedsReceiverCap));
+        }
+
+        #[ink::test]
+        fn references_are_indexed_per_receiver_and_paged_without_eviction() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let reference = b"invoice-7".to_vec();
+            let options = PaymentOptions { reference: reference.clone(), ..Default::default() };
+            let mut to_bob = Vec::new();
+            for _ in 0..MAX_BATCH_SIZE + 1 {
+                to_bob.push(
+                    send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options.clone())
+                        .expect("send failed"),
+                );
+            }
+            let to_eve = send_with(&mut contract, accounts.charlie, accounts.eve, AMOUNT, options)
+                .expect("send failed");
+
+            let first_page = contract
+                .find_by_reference(accounts.bob, reference.clone(), 0, MAX_BATCH_SIZE)
+                .expect("lookup failed");
+            assert_eq!(first_page, to_bob[..MAX_BATCH_SIZE as usize]);
+            let rest = contract
+                .find_by_reference(accounts.bob, reference.clone(), MAX_BATCH_SIZE, MAX_BATCH_SIZE)
+                .expect("lookup failed");
+            assert_eq!(rest, to_bob[MAX_BATCH_SIZE as usize..]);
+            assert_eq!(
+                contract.find_by_reference(accounts.eve, reference.clone(), 0, 1),
+                Ok(vec![to_eve])
+            );
+            assert_eq!(
+                contract.find_by_reference(accounts.eve, reference, 0, MAX_BATCH_SIZE + 1),
+                Err(Error::BatchTooLarge)
+            );
+        }
+
+        #[ink::test]
+        fn amount_range_scan_is_bounded_and_resumes_from_its_cursor() {
+            let mut contract = setup();
+            let accounts = accounts();
+            // same bucket as AMOUNT but outside the range, so every one is visited and skipped
+            let options = PaymentOptions::default();
+            for _ in 0..MAX_AMOUNT_SCAN {
+                send_with(&mut contract, accounts.alice, accounts.bob, 2 * AMOUNT, options.clone())
+                    .expect("send failed");
+            }
+            let wanted = send(&mut contract, accounts.charlie, accounts.bob);
+
+            let (page, cursor) = contract
+                .list_by_amount_range(AMOUNT, AMOUNT, None, MAX_BATCH_SIZE)
+                .expect("listing failed");
+            assert!(page.is_empty());
+            let cursor = cursor.expect("scan stopped without a cursor");
+
+            let (page, cursor) = contract
+                .list_by_amount_range(AMOUNT, AMOUNT, Some(cursor), MAX_BATCH_SIZE)
+                .expect("listing failed");
+            assert_eq!(page, vec![wanted]);
+            assert_eq!(cursor, None);
+        }
+
+        #[ink::test]
+        fn simulate_receive_refuses_agents_on_strict_receiver_payments() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let options = PaymentOptions { strict_receiver: true, ..Default::default() };
+            let strict = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+            let open = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.bob);
+            contract.approve_agent(accounts.charlie).expect("approve failed");
+
+            set_caller(accounts.charlie);
+            let otp = otp_of(&contract, open);
+            assert_eq!(contract.simulate_receive(open, otp), Ok(PaymentStatus::Success));
+            let otp = otp_of(&contract, strict);
+            assert_eq!(contract.simulate_receive(strict, otp), Err(Error::InvalidReceiver));
+            set_caller(accounts.bob);
+            assert_eq!(contract.simulate_receive(strict, otp), Ok(PaymentStatus::Success));
+        }
+
+        #[ink::test]
+        fn simulate_receive_is_refused_while_paused() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let otp = otp_of(&contract, id);
+            set_caller(accounts.alice);
+            contract.set_paused(true).expect("admin call failed");
+
+            set_caller(accounts.bob);
+            assert_eq!(contract.simulate_receive(id, otp), Err(Error::ContractPaused));
+            assert_eq!(contract.preview_receive_error(id, otp), Some(Error::ContractPaused));
+            set_caller(accounts.alice);
+            assert_eq!(contract.simulate_receive(id, otp), Err(Error::ContractPaused));
+
+            contract.set_paused(false).expect("admin call failed");
+            set_caller(accounts.bob);
+            assert_eq!(contract.simulate_receive(id, otp), Ok(PaymentStatus::Success));
+        }
+
+        #[ink::test]
+        fn event_counters_follow_a_full_payment_lifecycle() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let claimed = send(&mut contract, accounts.alice, accounts.bob);
+            let refunded = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.bob);
+            contract.acknowledge_payment(claimed).expect("acknowledge failed");
+            submit_wrong(&mut contract, claimed).expect("claim failed");
+            let otp = otp_of(&contract, claimed);
+            assert_eq!(submit(&mut contract, claimed, otp), Ok(ClaimOutcome::Paid));
+            advance_time(DEFAULT_EXPIRY_TIME);
+            set_caller(accounts.alice);
+            contract.get_refund(refunded).expect("refund failed");
+
+            assert_eq!(contract.emitted_event_count::<SecurePaymentRequested>(), 2);
+            assert_eq!(contract.emitted_event_count::<PaymentAcknowledged>(), 1);
+            assert_eq!(contract.emitted_event_count::<PaymentPaidOut>(), 1);
+            assert_eq!(contract.emitted_event_count::<PaymentRefunded>(), 1);
+            assert_eq!(contract.emitted_event_count::<DisputeRaised>(), 0);
+            // the counters agree with what the off-chain environment recorded
+            assert_eq!(
+                contract.emitted_event_count::<SecurePaymentInfo>() as usize,
+                emitted::<SecurePaymentInfo>()
+            );
+        }
+
+        #[ink::test]
+        fn ownership_transfer_moves_refund_rights_to_the_new_sender() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            assert_eq!(
+                contract.transfer_payment_ownership(id, contract_id()),
+                Err(Error::InvalidReceiver)
+            );
+            assert_eq!(contract.transfer_payment_ownership(id, accounts.charlie), Ok(()));
+            assert_eq!(emitted::<PaymentOwnershipTransferred>(), 1);
+            assert_eq!(
+                contract.transfer_payment_ownership(id, accounts.alice),
+                Err(Error::InvalidSender)
+            );
+
+            advance_time(DEFAULT_EXPIRY_TIME + 1);
+            assert_eq!(contract.get_refund(id), Err(Error::InvalidSender));
+            set_caller(accounts.charlie);
+            let before = balance_of(accounts.charlie);
+            assert_eq!(contract.get_refund(id), Ok(()));
+            assert_eq!(balance_of(accounts.charlie), before + AMOUNT);
+            contract.check_invariants(&[id]);
+        }
+
+        #[ink::test]
+        fn parse_otp_accepts_only_otp_digits_ascii_digits() {
+            assert_eq!(parse_otp(b"123456789"), Ok(123_456_789));
+            assert_eq!(parse_otp(OTP_MAX.to_string().as_bytes()), Ok(OTP_MAX));
+            assert_eq!(parse_otp(b"12345678a"), Err(Error::InvalidOtpFormat));
+            assert_eq!(parse_otp(b"-12345678"), Err(Error::InvalidOtpFormat));
+            assert_eq!(parse_otp(b" 12345678"), Err(Error::InvalidOtpFormat));
+            assert_eq!(parse_otp(b"12345678"), Err(Error::InvalidOtpFormat));
+            assert_eq!(parse_otp(b""), Err(Error::InvalidOtpFormat));
+            // more than u32::MAX, refused by length before it could overflow
+            assert_eq!(parse_otp(b"99999999999"), Err(Error::InvalidOtpFormat));
+        }
+
+        #[ink::test]
+        fn receive_payment_bytes_claims_with_the_otp_as_digits() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let otp = otp_of(&contract, id).to_string().into_bytes();
+            advance_time(1);
+            set_caller(accounts.bob);
+            assert_eq!(
+                contract.receive_payment_bytes(id, b"not a code".to_vec()),
+                Err(Error::InvalidOtpFormat)
+            );
+            assert_eq!(record(&contract, id).otp_attempts, 1);
+            assert_eq!(contract.receive_payment_bytes(id, otp), Ok(ClaimOutcome::Paid));
+            assert_eq!(record(&contract, id).status, PaymentStatus::Success);
+        }
+
+        #[ink::test]
+        fn otps_drawn_in_one_block_all_have_otp_digits_digits() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let mut otps = Vec::new();
+            for _ in 0..100 {
+                let id = send(&mut contract, accounts.alice, accounts.bob);
+                otps.push(otp_of(&contract, id));
+            }
+            for _ in 0..10_000 {
+                otps.push(contract.get_pseudo_random());
+            }
+            assert!(otps.iter().all(|otp| is_generated_otp(*otp)));
+            assert!(otps.iter().all(|otp| otp.to_string().len() == OTP_DIGITS));
+            // the same timestamp still gives a fresh OTP to every payment
+            let mut sent = otps[..100].to_vec();
+            sent.sort_unstable();
+            sent.dedup();
+            assert_eq!(sent.len(), 100);
+        }
+
+        #[ink::test]
+        fn payment_log_keeps_only_the_newest_events() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let owners = [accounts.alice, accounts.charlie];
+            let mut transferred_at = Vec::new();
+            for turn in 0..MAX_PAYMENT_EVENTS + 4 {
+                advance_time(10);
+                set_caller(owners[turn % 2]);
+                contract
+                    .transfer_payment_ownership(id, owners[(turn + 1) % 2])
+                    .expect("transfer failed");
+                transferred_at.push(now());
+            }
+
+            // the request and the first four transfers were evicted, oldest first
+            let log = contract.payment_events(id).expect("payment has no log");
+            assert_eq!(log.len(), MAX_PAYMENT_EVENTS);
+            let expected: Vec<_> = transferred_at[4..]
+                .iter()
+                .map(|at| (PaymentOwnershipTransferred::KIND, *at))
+                .collect();
+            assert_eq!(log, expected);
+            assert_eq!(emitted::<SecurePaymentRequested>(), 1);
+        }
+
+        #[ink::test]
+        fn receive_split_pays_every_destination_exactly() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let otp = otp_of(&contract, id);
+            let (charlie, django) = (balance_of(accounts.charlie), balance_of(accounts.django));
+            advance_time(1);
+            set_caller(accounts.bob);
+            let splits = vec![(accounts.charlie, AMOUNT / 4), (accounts.django, AMOUNT / 4 * 3)];
+            assert_eq!(contract.receive_split(id, otp, splits), Ok(ClaimOutcome::Paid));
+            assert_eq!(balance_of(accounts.charlie), charlie + AMOUNT / 4);
+            assert_eq!(balance_of(accounts.django), django + AMOUNT / 4 * 3);
+            assert_eq!(record(&contract, id).status, PaymentStatus::Success);
+            contract.check_invariants(&[id]);
+        }
+
+        #[ink::test]
+        fn receive_split_refuses_bad_split_lists_without_touching_the_payment() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let otp = otp_of(&contract, id);
+            let charlie = balance_of(accounts.charlie);
+            advance_time(1);
+            set_caller(accounts.bob);
+
+            let short = vec![(accounts.charlie, AMOUNT / 2), (accounts.django, AMOUNT / 4)];
+            assert_eq!(contract.receive_split(id, otp, short), Err(Error::BalanceMismatch));
+            let over = vec![(accounts.charlie, AMOUNT), (accounts.django, 1)];
+            assert_eq!(contract.receive_split(id, otp, over), Err(Error::BalanceMismatch));
+            let oversized = vec![(accounts.charlie, 1); MAX_BATCH_SIZE as usize + 1];
+            assert_eq!(contract.receive_split(id, otp, oversized), Err(Error::BatchTooLarge));
+            let to_zero = vec![(zero_address(), AMOUNT)];
+            assert_eq!(contract.receive_split(id, otp, to_zero), Err(Error::ZeroAddress));
+
+            let payment_info = record(&contract, id);
+            assert_eq!(payment_info.status, PaymentStatus::Waiting);
+            assert_eq!(payment_info.otp_attempts, 1);
+            assert_eq!(balance_of(accounts.charlie), charlie);
+            assert_eq!(contract.get_tracked_escrow(), AMOUNT);
+        }
+
+        #[ink::test]
+        fn idempotency_key_dedupes_retries_per_key() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let send_keyed = |contract: &mut PaymentContract, sender, key| {
+                let options = PaymentOptions { idempotency_key: Some(key), ..Default::default() };
+                send_with(contract, sender, accounts.bob, AMOUNT, options)
+            };
+            let first = send_keyed(&mut contract, accounts.alice, [1; 32]).expect("send failed");
+
+            // the retry gets the same id and its value back
+            let before = balance_of(accounts.alice);
+            assert_eq!(send_keyed(&mut contract, accounts.alice, [1; 32]), Ok(first));
+            assert_eq!(balance_of(accounts.alice), before);
+            assert_eq!(balance_of(contract_id()), AMOUNT);
+            assert_eq!(emitted::<SecurePaymentRequested>(), 1);
+
+            // keys are per sender
+            let second = send_keyed(&mut contract, accounts.alice, [2; 32]).expect("send failed");
+            let third = send_keyed(&mut contract, accounts.charlie, [1; 32]).expect("send failed");
+            assert!(second != first && third != first && third != second);
+            assert_eq!(emitted::<SecurePaymentRequested>(), 3);
+            assert_eq!(contract.get_tracked_escrow(), 3 * AMOUNT);
+            contract.check_invariants(&[first, second, third]);
+        }
+
+        #[ink::test]
+        fn dispute_blocks_refunds_until_the_admin_resolves_it() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.charlie);
+            assert_eq!(contract.raise_dispute(id), Err(Error::InvalidReceiver));
+            set_caller(accounts.bob);
+            assert_eq!(contract.raise_dispute(id), Ok(()));
+            assert_eq!(contract.raise_dispute(id), Err(Error::NotAllowed));
+            assert_eq!(emitted::<DisputeRaised>(), 1);
+
+            set_caller(accounts.alice);
+            assert_eq!(contract.cancel_payment(id), Err(Error::Disputed));
+            advance_time(DEFAULT_EXPIRY_TIME + 1);
+            assert_eq!(contract.can_refund(id), Ok(false));
+            assert_eq!(contract.get_refund(id), Err(Error::Disputed));
+            assert_eq!(contract.tick(1), Ok(1));
+            assert_eq!(record(&contract, id).status, PaymentStatus::Waiting);
+
+            set_caller(accounts.bob);
+            assert_eq!(contract.resolve_dispute(id), Err(Error::InvalidCaller));
+            set_caller(accounts.alice);
+            assert_eq!(contract.resolve_dispute(id), Ok(()));
+            assert!(!record(&contract, id).disputed);
+            let before = balance_of(accounts.alice);
+            assert_eq!(contract.get_refund(id), Ok(()));
+            assert_eq!(balance_of(accounts.alice), before + AMOUNT);
+            contract.check_invariants(&[id]);
+        }
+
+        #[ink::test]
+        fn settled_dispute_pays_the_receiver_net_of_fee() {
+            let mut contract = setup();
+            let accounts = accounts();
+            set_caller(accounts.alice);
+            contract.set_fee(100, accounts.django).expect("admin call failed");
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.bob);
+            contract.raise_dispute(id).expect("dispute failed");
+            advance_time(DEFAULT_EXPIRY_TIME + 1);
+
+            set_caller(accounts.alice);
+            let (bob, django) = (balance_of(accounts.bob), balance_of(accounts.django));
+            assert_eq!(contract.settle_dispute(id), Ok(()));
+            let fee = AMOUNT / 100;
+            assert_eq!(balance_of(accounts.bob), bob + AMOUNT - fee);
+            assert_eq!(balance_of(accounts.django), django + fee);
+            let payment_info = record(&contract, id);
+            assert_eq!(payment_info.status, PaymentStatus::Success);
+            assert!(!payment_info.disputed);
+            assert_eq!(contract.get_refund(id), Err(Error::NotAllowed));
+            assert_eq!(contract.settle_dispute(id), Err(Error::NotAllowed));
+            assert_eq!(emitted::<PaymentPaidOut>(), 1);
+            contract.check_invariants(&[id]);
+        }
+
+        #[ink::test]
+        fn claim_refuses_to_pay_out_more_than_tracked_escrow() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let otp = otp_of(&contract, id);
+            set_caller(accounts.alice);
+            contract.set_paused(true).expect("admin call failed");
+            contract.reconcile_escrow(AMOUNT - 1).expect("reconcile failed");
+            contract.set_paused(false).expect("admin call failed");
+
+            let bob = balance_of(accounts.bob);
+            assert_eq!(submit(&mut contract, id, otp), Err(Error::BalanceMismatch));
+            assert_eq!(balance_of(accounts.bob), bob);
+            assert_eq!(balance_of(contract_id()), AMOUNT);
+            assert_eq!(record(&contract, id).status, PaymentStatus::Waiting);
+            assert_eq!(emitted::<PaymentPaidOut>(), 0);
+        }
+
+        #[ink::test]
+        fn agents_are_capped_per_receiver_and_revoking_frees_a_slot() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let agents: Vec<_> = (1..=DEFAULT_MAX_AGENTS as u8 + 1)
+                .map(|n| AccountId::from([n; 32]))
+                .collect();
+            let (extra, agents_at_cap) = agents.split_last().expect("no agents");
+
+            set_caller(accounts.bob);
+            for agent in agents_at_cap {
+                assert_eq!(contract.approve_agent(*agent), Ok(()));
+            }
+            assert_eq!(contract.approve_agent(agents_at_cap[0]), Ok(()));
+            assert_eq!(contract.approve_agent(*extra), Err(Error::TooManyAgents));
+            assert_eq!(contract.get_agents(accounts.bob).len(), DEFAULT_MAX_AGENTS as usize);
+            // the cap is per receiver
+            set_caller(accounts.charlie);
+            assert_eq!(contract.approve_agent(*extra), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(contract.revoke_agent(*extra), Err(Error::NotAllowed));
+            assert_eq!(contract.revoke_agent(agents_at_cap[0]), Ok(()));
+            assert_eq!(contract.approve_agent(*extra), Ok(()));
+            assert!(!contract.get_agents(accounts.bob).contains(&agents_at_cap[0]));
+            assert!(contract.get_agents(accounts.bob).contains(extra));
+        }
+
+        #[ink::test]
+        fn receive_payment_to_pays_and_reports_the_chosen_account() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let otp = otp_of(&contract, id);
+            let (bob, django) = (balance_of(accounts.bob), balance_of(accounts.django));
+            advance_time(1);
+            set_caller(accounts.bob);
+            assert_eq!(
+                contract.receive_payment_to(id, otp, zero_address()),
+                Err(Error::ZeroAddress)
+            );
+            assert_eq!(
+                contract.receive_payment_to(id, otp, contract_id()),
+                Err(Error::InvalidReceiver)
+            );
+            let claimed = contract.receive_payment_to(id, otp, accounts.django);
+            assert_eq!(claimed, Ok(ClaimOutcome::Paid));
+
+            assert_eq!(balance_of(accounts.django), django + AMOUNT);
+            assert_eq!(balance_of(accounts.bob), bob);
+            let paid_out = decoded::<PaymentPaidOut>();
+            assert_eq!(paid_out.len(), 1);
+            assert_eq!(paid_out[0].receiver, accounts.bob);
+            assert_eq!(paid_out[0].payout_to, accounts.django);
+            assert_eq!((paid_out[0].amount, paid_out[0].fee), (AMOUNT, 0));
+            contract.check_invariants(&[id]);
+        }
+
+        #[ink::test]
+        fn acknowledgement_is_announced_once() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            assert_eq!(contract.acknowledge_payment(id), Err(Error::InvalidReceiver));
+
+            set_caller(accounts.bob);
+            assert_eq!(contract.acknowledge_payment(id), Ok(()));
+            assert_eq!(record(&contract, id).status, PaymentStatus::Acknowledged);
+            assert_eq!(contract.acknowledge_payment(id), Err(Error::NotAllowed));
+            let acknowledged = decoded::<PaymentAcknowledged>();
+            assert_eq!(acknowledged.len(), 1);
+            assert_eq!(acknowledged[0].payment_id, id);
+            assert_eq!(acknowledged[0].sender, accounts.alice);
+            assert_eq!(acknowledged[0].receiver, accounts.bob);
+        }
+
+        #[ink::test]
+        fn send_reports_each_amount_problem_precisely() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            contract.set_max_amount(Some(2 * AMOUNT)).expect("admin call failed");
+            assert_eq!(send_funded(&mut contract, bob, 0, 0), Err(Error::ZeroBalance));
+            assert_eq!(
+                send_funded(&mut contract, bob, AMOUNT, AMOUNT - 1),
+                Err(Error::BalanceMismatch)
+            );
+            assert_eq!(
+                send_funded(&mut contract, bob, AMOUNT - 1, AMOUNT - 1),
+                Err(Error::BelowThresholdValue)
+            );
+            assert_eq!(
+                send_funded(&mut contract, bob, 2 * AMOUNT + 1, 2 * AMOUNT + 1),
+                Err(Error::AboveMaxAmount)
+            );
+            assert_eq!(send_funded(&mut contract, bob, 2 * AMOUNT, 2 * AMOUNT), Ok(()));
+        }
+
+        #[ink::test]
+        fn send_amount_errors_follow_a_fixed_precedence() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            // a mismatch is reported before a zero, low or high amount
+            assert_eq!(send_funded(&mut contract, bob, 0, 1), Err(Error::BalanceMismatch));
+            assert_eq!(
+                send_funded(&mut contract, bob, AMOUNT - 1, AMOUNT),
+                Err(Error::BalanceMismatch)
+            );
+            contract.set_max_amount(Some(AMOUNT / 2)).expect("admin call failed");
+            assert_eq!(
+                send_funded(&mut contract, bob, AMOUNT + 1, AMOUNT),
+                Err(Error::BalanceMismatch)
+            );
+            // under the threshold comes before over the maximum
+            assert_eq!(
+                send_funded(&mut contract, bob, AMOUNT - 1, AMOUNT - 1),
+                Err(Error::BelowThresholdValue)
+            );
+            assert_eq!(
+                send_funded(&mut contract, bob, AMOUNT, AMOUNT),
+                Err(Error::AboveMaxAmount)
+            );
+            // and the receiver and pause checks come before any amount check
+            assert_eq!(
+                send_funded(&mut contract, zero_address(), 0, 1),
+                Err(Error::ZeroAddress)
+            );
+            contract.set_paused(true).expect("admin call failed");
+            assert_eq!(send_funded(&mut contract, bob, 0, 1), Err(Error::ContractPaused));
+        }
+
+        #[ink::test]
+        fn settling_a_payment_frees_room_under_the_tvl_cap() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_max_tvl(Some(3 * AMOUNT)).expect("admin call failed");
+            let ids: Vec<_> =
+                (0..3).map(|_| send(&mut contract, accounts.alice, accounts.bob)).collect();
+            assert_eq!(contract.get_tracked_escrow(), 3 * AMOUNT);
+            let over = send_amount(&mut contract, accounts.charlie, accounts.bob, AMOUNT);
+            assert_eq!(over, Err(Error::TvlCapExceeded));
+
+            let otp = otp_of(&contract, ids[0]);
+            assert_eq!(submit(&mut contract, ids[0], otp), Ok(ClaimOutcome::Paid));
+            let fourth = send_amount(&mut contract, accounts.charlie, accounts.bob, AMOUNT)
+                .expect("send failed");
+            assert_eq!(contract.get_tracked_escrow(), 3 * AMOUNT);
+            contract.check_invariants(&[ids[0], ids[1], ids[2], fourth]);
+        }
+
+        #[ink::test]
+        fn exactly_one_of_claim_and_refund_is_valid_at_the_deadline() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let claimed = send(&mut contract, accounts.alice, accounts.bob);
+            let refunded = send(&mut contract, accounts.alice, accounts.bob);
+            let expires_at = record(&contract, claimed).expires_at;
+            assert_eq!(expires_at, START + DEFAULT_EXPIRY_TIME);
+
+            // at the deadline itself the payment is still the receiver's
+            test::set_block_timestamp::<Env>(expires_at);
+            set_caller(accounts.alice);
+            assert_eq!(contract.can_refund(claimed), Ok(false));
+            assert_eq!(contract.get_refund(claimed), Err(Error::NotAllowed));
+            set_caller(accounts.bob);
+            let otp = otp_of(&contract, claimed);
+            assert_eq!(contract.receive_payment(claimed, otp), Ok(ClaimOutcome::Paid));
+
+            // one millisecond later it is the sender's
+            test::set_block_timestamp::<Env>(expires_at + 1);
+            let otp = otp_of(&contract, refunded);
+            set_caller(accounts.alice);
+            assert_eq!(contract.can_refund(refunded), Ok(true));
+            set_caller(accounts.bob);
+            assert_eq!(contract.receive_payment(refunded, otp), Ok(ClaimOutcome::Expired));
+            set_caller(accounts.alice);
+            assert_eq!(contract.get_refund(refunded), Ok(()));
+            contract.check_invariants(&[claimed, refunded]);
+        }
+
+        #[ink::test]
+        fn clock_skew_grace_moves_the_expiry_boundary() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let grace = 60_000;
+            assert_eq!(
+                contract.set_clock_skew_grace(MAX_CLOCK_SKEW_GRACE + 1),
+                Err(Error::ClockSkewGraceTooLarge)
+            );
+            contract.set_clock_skew_grace(grace).expect("admin call failed");
+            let claimed = send(&mut contract, accounts.alice, accounts.bob);
+            let refunded = send(&mut contract, accounts.alice, accounts.bob);
+            let expires_at = record(&contract, claimed).expires_at;
+
+            // past the deadline but within the grace, only the claim is valid
+            test::set_block_timestamp::<Env>(expires_at + grace);
+            set_caller(accounts.alice);
+            assert_eq!(contract.can_refund(refunded), Ok(false));
+            assert_eq!(contract.get_refund(refunded), Err(Error::NotAllowed));
+            set_caller(accounts.bob);
+            let otp = otp_of(&contract, claimed);
+            assert_eq!(contract.receive_payment(claimed, otp), Ok(ClaimOutcome::Paid));
+
+            // past the grace, only the refund is
+            test::set_block_timestamp::<Env>(expires_at + grace + 1);
+            let otp = otp_of(&contract, refunded);
+            assert_eq!(contract.receive_payment(refunded, otp), Ok(ClaimOutcome::Expired));
+            set_caller(accounts.alice);
+            assert_eq!(contract.get_refund(refunded), Ok(()));
+            contract.check_invariants(&[claimed, refunded]);
+        }
+
+        #[ink::test]
+        fn lowering_the_expiry_leaves_in_flight_payments_alone() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let in_flight = send(&mut contract, accounts.alice, accounts.bob);
+            advance_time(10_000);
+            set_caller(accounts.alice);
+            assert_eq!(contract.set_expiry_period(0), Err(Error::InvalidExpiry));
+            contract.set_expiry_period(1_000).expect("admin call failed");
+            let new = send(&mut contract, accounts.alice, accounts.bob);
+            assert_eq!(record(&contract, in_flight).expires_at, START + DEFAULT_EXPIRY_TIME);
+            assert_eq!(record(&contract, new).expires_at, now() + 1_000);
+
+            // long past the new window, the old payment can still be claimed
+            advance_time(5_000);
+            set_caller(accounts.alice);
+            assert_eq!(contract.can_refund(in_flight), Ok(false));
+            assert_eq!(contract.can_refund(new), Ok(true));
+            let otp = otp_of(&contract, in_flight);
+            assert_eq!(submit(&mut contract, in_flight, otp), Ok(ClaimOutcome::Paid));
+        }
+
+        #[ink::test]
+        fn a_clock_behind_the_send_time_reads_as_fresh() {
+            let mut contract = setup();
+            let accounts = accounts();
+            advance_time(5_000);
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            advance_time(2_000);
+            assert_eq!(contract.payment_age(id), Ok(2_000));
+            assert_eq!(contract.time_until_expiry(id), Ok(DEFAULT_EXPIRY_TIME - 2_000));
+
+            // the clock jumps back to before the payment was recorded
+            test::set_block_timestamp::<Env>(START);
+            assert_eq!(contract.payment_age(id), Ok(0));
+            assert_eq!(contract.time_until_expiry(id), Ok(DEFAULT_EXPIRY_TIME));
+            set_caller(accounts.alice);
+            assert_eq!(contract.can_refund(id), Ok(false));
+            assert_eq!(contract.get_refund(id), Err(Error::NotAllowed));
+            assert_eq!(record(&contract, id).status, PaymentStatus::Waiting);
+        }
+
+        #[ink::test]
+        fn try_new_refuses_only_the_zero_admin() {
+            let accounts = accounts();
+            let contract = PaymentContract::try_new(accounts.alice).expect("constructor failed");
+            assert_eq!(contract.dashboard().admin, accounts.alice);
+            assert!(matches!(
+                PaymentContract::try_new(zero_address()),
+                Err(Error::InvalidCaller)
+            ));
+        }
+
+        #[ink::test]
+        fn reconcile_escrow_needs_the_admin_and_a_pause() {
+            let mut contract = setup();
+            let accounts = accounts();
+            send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            assert_eq!(contract.reconcile_escrow(2 * AMOUNT), Err(Error::NotPaused));
+            contract.set_paused(true).expect("admin call failed");
+            set_caller(accounts.bob);
+            assert_eq!(contract.reconcile_escrow(2 * AMOUNT), Err(Error::InvalidCaller));
+            assert_eq!(contract.get_tracked_escrow(), AMOUNT);
+            assert_eq!(emitted::<ReconciledEscrow>(), 0);
+
+            set_caller(accounts.alice);
+            assert_eq!(contract.reconcile_escrow(2 * AMOUNT), Ok(()));
+            assert_eq!(contract.get_tracked_escrow(), 2 * AMOUNT);
+            let reconciled = decoded::<ReconciledEscrow>();
+            assert_eq!(reconciled.len(), 1);
+            assert_eq!((reconciled[0].old_value, reconciled[0].new_value), (AMOUNT, 2 * AMOUNT));
+        }
+
+        #[ink::test]
+        fn priority_is_a_topic_of_the_request_event() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let options = PaymentOptions { priority: true, ..Default::default() };
+            let flagged = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+            let plain = send(&mut contract, accounts.alice, accounts.bob);
+            assert!(record(&contract, flagged).priority);
+            assert!(!record(&contract, plain).priority);
+
+            // topics: signature, sender, receiver, priority; short values are zero padded
+            let signature = <SecurePaymentRequested as ink::env::Event>::SIGNATURE_TOPIC
+                .expect("event is anonymous");
+            let priority_topics: Vec<_> = test::recorded_events()
+                .filter(|event| event.topics[0][..] == signature)
+                .map(|event| event.topics[3].clone())
+                .collect();
+            let topic = |flag: bool| {
+                let mut topic = vec![0u8; 32];
+                topic[0] = u8::from(flag);
+                topic
+            };
+            assert_eq!(priority_topics, vec![topic(true), topic(false)]);
+        }
+
+        #[ink::test]
+        fn status_counts_follow_every_transition() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let ids: Vec<_> =
+                (0..6).map(|_| send(&mut contract, accounts.alice, accounts.bob)).collect();
+            assert_eq!(contract.status_counts(), (6, 0, 0, 0, 0));
+
+            set_caller(accounts.bob);
+            contract.acknowledge_payment(ids[1]).expect("acknowledge failed");
+            let otp = otp_of(&contract, ids[2]);
+            submit(&mut contract, ids[2], otp).expect("claim failed");
+            for _ in 0..ATTEMPTS_LIMIT {
+                submit_wrong(&mut contract, ids[5]).expect("claim failed");
+            }
+            assert_eq!(contract.status_counts(), (4, 1, 0, 0, 1));
+
+            advance_time(DEFAULT_EXPIRY_TIME);
+            let otp = otp_of(&contract, ids[3]);
+            assert_eq!(submit(&mut contract, ids[3], otp), Ok(ClaimOutcome::Expired));
+            set_caller(accounts.alice);
+            contract.get_refund(ids[4]).expect("refund failed");
+            assert_eq!(contract.status_counts(), (2, 1, 1, 1, 1));
+
+            // an expired payment leaves expired when it is refunded
+            contract.get_refund(ids[3]).expect("refund failed");
+            assert_eq!(contract.status_counts(), (2, 1, 2, 0, 1));
+            contract.check_invariants(&ids);
+        }
+
+        #[ink::test]
+        fn otp_hash_is_for_the_sender_and_admin_only() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.charlie, accounts.bob);
+            let otp = otp_of(&contract, id);
+            let mut expected = <Sha2x256 as HashOutput>::Type::default();
+            ink::env::hash_encoded::<Sha2x256, _>(&otp, &mut expected);
+
+            set_caller(accounts.charlie);
+            assert_eq!(contract.get_otp_hash(id), Ok(expected));
+            set_caller(accounts.alice);
+            assert_eq!(contract.get_otp_hash(id), Ok(expected));
+            set_caller(accounts.bob);
+            assert_eq!(contract.get_otp_hash(id), Err(Error::InvalidCaller));
+            set_caller(accounts.django);
+            assert_eq!(contract.get_otp_hash(id), Err(Error::InvalidCaller));
+            let missing = Hash::from([7; 32]);
+            assert_eq!(contract.get_otp_hash(missing), Err(Error::PaymentRecordMissing));
+        }
+
+        #[ink::test]
+        fn get_records_keeps_input_order_and_blanks_otps() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let first = send(&mut contract, accounts.alice, accounts.bob);
+            let second = send_amount(&mut contract, accounts.charlie, accounts.django, 2 * AMOUNT)
+                .expect("send failed");
+            let missing = Hash::from([7; 32]);
+
+            let records = contract
+                .get_records(vec![second, missing, first, missing])
+                .expect("get_records failed");
+            assert_eq!(records.len(), 4);
+            assert!(records[1].is_none() && records[3].is_none());
+            let second_info = records[0].as_ref().expect("record missing");
+            let first_info = records[2].as_ref().expect("record missing");
+            assert_eq!((second_info.sender, second_info.amount), (accounts.charlie, 2 * AMOUNT));
+            assert_eq!((first_info.sender, first_info.amount), (accounts.alice, AMOUNT));
+            assert_eq!((first_info.otp, second_info.otp), (0, 0));
+
+            assert!(contract.get_records(Vec::new()).is_ok_and(|records| records.is_empty()));
+            let too_many = vec![first; MAX_BATCH_SIZE as usize + 1];
+            assert!(matches!(contract.get_records(too_many), Err(Error::BatchTooLarge)));
+        }
+
+        #[ink::test]
+        fn zero_address_checks_follow_the_admin_switch() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let refused = send_amount(&mut contract, accounts.alice, zero_address(), AMOUNT);
+            assert_eq!(refused, Err(Error::ZeroAddress));
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            assert_eq!(
+                contract.transfer_payment_ownership(id, zero_address()),
+                Err(Error::ZeroAddress)
+            );
+            set_caller(accounts.bob);
+            assert_eq!(contract.set_allow_zero_address(true), Err(Error::InvalidCaller));
+
+            set_caller(accounts.alice);
+            contract.set_allow_zero_address(true).expect("admin call failed");
+            let to_zero = send_amount(&mut contract, accounts.alice, zero_address(), AMOUNT)
+                .expect("send failed");
+            assert_eq!(record(&contract, to_zero).receiver, zero_address());
+            set_caller(accounts.alice);
+            assert_eq!(contract.transfer_payment_ownership(id, zero_address()), Ok(()));
+
+            contract.set_allow_zero_address(false).expect("admin call failed");
+            let refused = send_amount(&mut contract, accounts.alice, zero_address(), AMOUNT);
+            assert_eq!(refused, Err(Error::ZeroAddress));
+        }
+
+        #[ink::test]
+        fn effective_threshold_resolves_overrides_and_exemptions() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let (alice, bob, charlie) = (accounts.alice, accounts.bob, accounts.charlie);
+            assert_eq!(contract.effective_threshold(alice, bob), Ok(DEFAULT_THRESHOLD));
+
+            contract.set_receiver_threshold(bob, Some(3 * AMOUNT)).expect("admin call failed");
+            assert_eq!(contract.effective_threshold(alice, bob), Ok(3 * AMOUNT));
+            assert_eq!(contract.effective_threshold(alice, charlie), Ok(DEFAULT_THRESHOLD));
+            let below = send_amount(&mut contract, alice, bob, 2 * AMOUNT);
+            assert_eq!(below, Err(Error::BelowThresholdValue));
+
+            // an exempt sender beats a receiver override
+            set_caller(alice);
+            contract.set_trusted_sender(charlie, true).expect("admin call failed");
+            assert_eq!(contract.effective_threshold(charlie, bob), Ok(0));
+            assert!(send_amount(&mut contract, charlie, bob, 1).is_ok());
+
+            set_caller(alice);
+            contract.set_receiver_threshold(bob, None).expect("admin call failed");
+            contract.set_trusted_sender(charlie, false).expect("admin call failed");
+            assert_eq!(contract.effective_threshold(charlie, bob), Ok(DEFAULT_THRESHOLD));
+        }
+
+        #[ink::test]
+        fn receiver_cap_admits_payments_up_to_and_at_the_cap() {
+            let mut contract = setup();
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            contract.set_my_receive_cap(Some(2 * AMOUNT)).expect("cap failed");
+
+            assert!(send_amount(&mut contract, accounts.alice, accounts.bob, AMOUNT).is_ok());
+            assert!(send_amount(&mut contract, accounts.alice, accounts.bob, 2 * AMOUNT).is_ok());
+            let over = send_amount(&mut contract, accounts.alice, accounts.bob, 2 * AMOUNT + 1);
+            assert_eq!(over, Err(Error::ExceedsReceiverCap));
+            // the cap is the receiver's own
+            let other = send_amount(&mut contract, accounts.alice, accounts.charlie, 3 * AMOUNT);
+            assert!(other.is_ok());
+
+            set_caller(accounts.bob);
+            contract.set_my_receive_cap(None).expect("cap failed");
+            assert!(send_amount(&mut contract, accounts.alice, accounts.bob, 3 * AMOUNT).is_ok());
+        }
+
+        #[ink::test]
+        fn pause_and_unpause_are_announced() {
+            let mut contract = setup();
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            assert_eq!(contract.set_paused(true), Err(Error::InvalidCaller));
+            set_caller(accounts.alice);
+            contract.set_paused(true).expect("admin call failed");
+            assert!(contract.is_paused());
+            contract.set_paused(false).expect("admin call failed");
+            assert!(!contract.is_paused());
+
+            let toggled = decoded::<PauseToggled>();
+            assert_eq!(toggled.len(), 2);
+            assert_eq!((toggled[0].paused, toggled[0].admin), (true, accounts.alice));
+            assert_eq!((toggled[1].paused, toggled[1].admin), (false, accounts.alice));
+        }
+
+        #[ink::test]
+        fn expiry_seconds_convert_to_milliseconds() {
+            let mut contract = setup();
+            contract.set_expiry_seconds(86_400).expect("admin call failed");
+            assert_eq!(contract.expiry_time, DEFAULT_EXPIRY_TIME);
+            // the largest u32 second count still fits a millisecond Timestamp
+            contract.set_expiry_seconds(u32::MAX).expect("admin call failed");
+            assert_eq!(contract.expiry_time, Timestamp::from(u32::MAX) * 1000);
+            assert_eq!(contract.set_expiry_seconds(0), Err(Error::InvalidExpiry));
+            set_caller(accounts().bob);
+            assert_eq!(contract.set_expiry_seconds(60), Err(Error::InvalidCaller));
+            assert_eq!(contract.expiry_time, Timestamp::from(u32::MAX) * 1000);
+        }
+
+        #[ink::test]
+        fn sender_locked_total_overflow_is_an_error() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.charlie, accounts.bob);
+            let nearly_full = Balance::MAX - AMOUNT + 1;
+            contract.sender_locked.insert(accounts.alice, &nearly_full);
+
+            let sent = send_amount(&mut contract, accounts.alice, accounts.bob, AMOUNT);
+            assert_eq!(sent, Err(Error::Overflow));
+            set_caller(accounts.charlie);
+            assert_eq!(
+                contract.transfer_payment_ownership(id, accounts.alice),
+                Err(Error::Overflow)
+            );
+            assert_eq!(contract.sender_locked_total(accounts.alice), nearly_full);
+            assert_eq!(contract.sender_locked_total(accounts.charlie), AMOUNT);
+            assert_eq!(record(&contract, id).sender, accounts.charlie);
+        }
+
+        #[ink::test]
+        fn callback_data_is_stored_up_to_its_bound() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let with_data = |len| PaymentOptions {
+                callback_data: vec![0xAB; len],
+                ..Default::default()
+            };
+            let options = with_data(MAX_CALLBACK_DATA_LEN);
+            let id = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+            assert_eq!(record(&contract, id).callback_data, vec![0xAB; MAX_CALLBACK_DATA_LEN]);
+            let options = with_data(MAX_CALLBACK_DATA_LEN + 1);
+            let too_long = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options);
+            assert_eq!(too_long, Err(Error::CallbackDataTooLong));
+        }
+
+        #[ink::test]
+        fn symbol_round_trips_and_defaults_to_the_native_ticker() {
+            let mut contract = setup();
+            assert_eq!(contract.get_symbol(), DEFAULT_SYMBOL);
+            let symbol = *b"USDT\0\0\0\0";
+            assert_eq!(contract.set_symbol(symbol), Ok(()));
+            assert_eq!(contract.get_symbol(), symbol);
+            set_caller(accounts().bob);
+            assert_eq!(contract.set_symbol(DEFAULT_SYMBOL), Err(Error::InvalidCaller));
+            assert_eq!(contract.get_symbol(), symbol);
+        }
+
+        #[ink::test]
+        fn can_refund_matches_get_refund() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            assert_eq!(contract.can_refund(id), Ok(false));
+            assert_eq!(contract.get_refund(id), Err(Error::NotAllowed));
+
+            advance_time(DEFAULT_EXPIRY_TIME + 1);
+            set_caller(accounts.bob);
+            assert_eq!(contract.can_refund(id), Ok(false));
+            assert_eq!(contract.get_refund(id), Err(Error::InvalidSender));
+            set_caller(accounts.alice);
+            assert_eq!(contract.can_refund(id), Ok(true));
+            assert_eq!(contract.get_refund(id), Ok(()));
+            assert_eq!(contract.can_refund(id), Ok(false));
+            let missing = Hash::from([7; 32]);
+            assert_eq!(contract.can_refund(missing), Err(Error::PaymentRecordMissing));
+        }
+
+        #[ink::test]
+        fn voucher_matches_the_stored_record() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send_amount(&mut contract, accounts.alice, accounts.bob, 2 * AMOUNT)
+                .expect("send failed");
+            let payment_info = record(&contract, id);
+            let voucher = contract.export_voucher(id).expect("export failed");
+            assert_eq!(
+                voucher,
+                PaymentVoucher {
+                    payment_id: id,
+                    amount: payment_info.amount,
+                    receiver: payment_info.receiver,
+                    expires_at: payment_info.expires_at,
+                }
+            );
+            assert_eq!(voucher.expires_at, now() + contract.time_until_expiry(id).expect("missing"));
+            let missing = Hash::from([7; 32]);
+            assert_eq!(contract.export_voucher(missing), Err(Error::PaymentRecordMissing));
+        }
+
+        #[ink::test]
+        fn claim_and_cancel_in_one_block_pay_out_once() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let claimed = send(&mut contract, accounts.alice, accounts.bob);
+            let cancelled = send(&mut contract, accounts.alice, accounts.bob);
+            let (alice, bob) = (balance_of(accounts.alice), balance_of(accounts.bob));
+            advance_time(1);
+
+            // claim first: the cancel that follows in the same block finds it settled
+            set_caller(accounts.bob);
+            let otp = otp_of(&contract, claimed);
+            assert_eq!(contract.receive_payment(claimed, otp), Ok(ClaimOutcome::Paid));
+            set_caller(accounts.alice);
+            assert_eq!(contract.cancel_payment(claimed), Err(Error::NotAllowed));
+
+            // cancel first: the claim that follows finds it refunded
+            assert_eq!(contract.cancel_payment(cancelled), Ok(()));
+            set_caller(accounts.bob);
+            let otp = otp_of(&contract, cancelled);
+            let late = contract.receive_payment(cancelled, otp);
+            assert_eq!(late, Err(Error::AlreadyReceivedPayment));
+
+            assert_eq!(balance_of(accounts.bob), bob + AMOUNT);
+            assert_eq!(balance_of(accounts.alice), alice + AMOUNT);
+            assert_eq!(balance_of(contract_id()), 0);
+            contract.check_invariants(&[claimed, cancelled]);
+        }
+
+        #[ink::test]
+        fn counterparties_are_sender_then_receiver() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.charlie, accounts.bob);
+            assert_eq!(contract.counterparties(id), Ok((accounts.charlie, accounts.bob)));
+            let missing = Hash::from([7; 32]);
+            assert_eq!(contract.counterparties(missing), Err(Error::PaymentRecordMissing));
+        }
+
+        #[ink::test]
+        fn contract_address_is_the_callee() {
+            let contract = setup();
+            assert_eq!(contract.contract_address(), contract_id());
+            assert_eq!(contract.contract_address(), test::callee::<Env>());
+        }
+
+        #[ink::test]
+        fn receiver_success_count_counts_only_paid_claims() {
+            let mut contract = setup();
+            let accounts = accounts();
+            for _ in 0..3 {
+                let id = send(&mut contract, accounts.alice, accounts.bob);
+                let otp = otp_of(&contract, id);
+                assert_eq!(submit(&mut contract, id, otp), Ok(ClaimOutcome::Paid));
+            }
+            let failed = send(&mut contract, accounts.alice, accounts.bob);
+            submit_wrong(&mut contract, failed).expect("claim failed");
+            let other = send(&mut contract, accounts.alice, accounts.charlie);
+            let otp = otp_of(&contract, other);
+            assert_eq!(submit(&mut contract, other, otp), Ok(ClaimOutcome::Paid));
+
+            assert_eq!(contract.receiver_success_count(accounts.bob), 3);
+            assert_eq!(contract.receiver_success_count(accounts.charlie), 1);
+            assert_eq!(contract.receiver_success_count(accounts.django), 0);
+        }
+
+        #[ink::test]
+        fn set_windows_updates_both_or_neither() {
+            let mut contract = setup();
+            assert_eq!(contract.set_windows(5_000, 2_000), Ok(()));
+            assert_eq!((contract.expiry_time, contract.refund_delay), (5_000, 2_000));
+            let changed = decoded::<ConfigChanged>();
+            assert_eq!(changed.len(), 1);
+            assert_eq!((changed[0].claim_window, changed[0].refund_delay), (5_000, 2_000));
+
+            assert_eq!(contract.set_windows(0, 1_000), Err(Error::InvalidExpiry));
+            assert_eq!(contract.set_windows(Timestamp::MAX, 1), Err(Error::Overflow));
+            set_caller(accounts().bob);
+            assert_eq!(contract.set_windows(7_000, 0), Err(Error::InvalidCaller));
+            assert_eq!((contract.expiry_time, contract.refund_delay), (5_000, 2_000));
+            assert_eq!(emitted::<ConfigChanged>(), 1);
+        }
+
+        #[ink::test]
+        fn otp_format_check_matches_the_generated_range() {
+            let mut contract = setup();
+            for otp in [OTP_MIN, OTP_MIN + 1, 123_456_789, OTP_MAX] {
+                assert!(contract.is_valid_otp_format(otp), "{otp} refused");
+            }
+            for otp in [0, 1, OTP_MIN - 1, OTP_MAX + 1, u32::MAX] {
+                assert!(!contract.is_valid_otp_format(otp), "{otp} accepted");
+            }
+            // the bounds follow OTP_DIGITS rather than being hard-coded
+            assert_eq!(OTP_MIN.to_string().len(), OTP_DIGITS);
+            assert_eq!((OTP_MIN - 1).to_string().len(), OTP_DIGITS - 1);
+            assert_eq!(OTP_MAX.to_string().len(), OTP_DIGITS);
+            assert_eq!((OTP_MAX + 1).to_string().len(), OTP_DIGITS + 1);
+            for _ in 0..1_000 {
+                let otp = contract.get_pseudo_random();
+                assert!(contract.is_valid_otp_format(otp));
+            }
+        }
+
+        #[ink::test]
+        fn claims_wait_for_min_confirmations() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_min_confirmations(2).expect("admin call failed");
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let otp = otp_of(&contract, id);
+
+            assert_eq!(submit(&mut contract, id, otp), Err(Error::NotYetConfirmed));
+            test::advance_block::<Env>();
+            assert_eq!(submit(&mut contract, id, otp), Err(Error::NotYetConfirmed));
+            assert_eq!(record(&contract, id).otp_attempts, 1);
+            test::advance_block::<Env>();
+            assert_eq!(submit(&mut contract, id, otp), Ok(ClaimOutcome::Paid));
+
+            set_caller(accounts.bob);
+            assert_eq!(contract.set_min_confirmations(0), Err(Error::InvalidCaller));
+        }
+
+        #[ink::test]
+        fn verify_id_spots_a_diverged_record() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let other = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            assert_eq!(contract.verify_id(id), Ok(true));
+            // lifecycle changes are not divergence
+            submit_wrong(&mut contract, id).expect("claim failed");
+            set_caller(accounts.bob);
+            contract.acknowledge_payment(other).expect("acknowledge failed");
+            assert_eq!(contract.verify_id(id), Err(Error::InvalidCaller));
+            set_caller(accounts.alice);
+            assert_eq!(contract.verify_id(id), Ok(true));
+            assert_eq!(contract.verify_id(other), Ok(true));
+
+            let mut diverged = record(&contract, id);
+            diverged.amount += 1;
+            contract.payment_records.insert(id, &diverged);
+            assert_eq!(contract.verify_id(id), Ok(false));
+            let missing = Hash::from([7; 32]);
+            assert_eq!(contract.verify_id(missing), Err(Error::PaymentRecordMissing));
+        }
+
+        #[ink::test]
+        fn memos_round_trip_with_their_encryption_flag() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let memo = |reference: &[u8], reference_encrypted| PaymentOptions {
+                reference: reference.to_vec(),
+                reference_encrypted,
+                ..Default::default()
+            };
+            let blob = [0x9F, 0x00, 0xFF, 0x13];
+            let options = memo(&blob, true);
+            let sealed = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+            let options = memo(b"rent march", false);
+            let plain = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+
+            let sealed_info = contract.view_payment_record(sealed);
+            assert_eq!(sealed_info.reference, blob.to_vec());
+            assert!(sealed_info.reference_encrypted);
+            let plain_info = contract.view_payment_record(plain);
+            assert_eq!(plain_info.reference, b"rent march".to_vec());
+            assert!(!plain_info.reference_encrypted);
+        }
+
+        #[ink::test]
+        fn auto_acknowledge_applies_to_new_payments_only() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let before = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.bob);
+            contract.set_auto_acknowledge(true).expect("opt-in failed");
+            let on = send(&mut contract, accounts.alice, accounts.bob);
+            let other = send(&mut contract, accounts.alice, accounts.charlie);
+            assert_eq!(record(&contract, before).status, PaymentStatus::Waiting);
+            assert_eq!(record(&contract, on).status, PaymentStatus::Acknowledged);
+            assert_eq!(record(&contract, other).status, PaymentStatus::Waiting);
+            assert_eq!(emitted::<PaymentAcknowledged>(), 1);
+
+            set_caller(accounts.bob);
+            contract.set_auto_acknowledge(false).expect("opt-out failed");
+            let off = send(&mut contract, accounts.alice, accounts.bob);
+            assert_eq!(record(&contract, off).status, PaymentStatus::Waiting);
+            // an auto-acknowledged payment still claims normally
+            let otp = otp_of(&contract, on);
+            assert_eq!(submit(&mut contract, on, otp), Ok(ClaimOutcome::Paid));
+            contract.check_invariants(&[before, on, other, off]);
+        }
+
+        #[ink::test]
+        fn strict_receiver_blocks_agents_and_redirection_on_claims() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let options = PaymentOptions { strict_receiver: true, ..Default::default() };
+            let strict = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+            let open = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.bob);
+            contract.approve_agent(accounts.charlie).expect("approve failed");
+            advance_time(1);
+
+            let bob = balance_of(accounts.bob);
+            set_caller(accounts.charlie);
+            let otp = otp_of(&contract, strict);
+            assert_eq!(contract.receive_payment(strict, otp), Err(Error::InvalidReceiver));
+            let otp = otp_of(&contract, open);
+            assert_eq!(contract.receive_payment(open, otp), Ok(ClaimOutcome::Paid));
+            assert_eq!(balance_of(accounts.bob), bob + AMOUNT);
+
+            set_caller(accounts.bob);
+            let otp = otp_of(&contract, strict);
+            let redirected = contract.receive_payment_to(strict, otp, accounts.django);
+            assert_eq!(redirected, Err(Error::InvalidReceiver));
+            let split = vec![(accounts.bob, AMOUNT / 2), (accounts.django, AMOUNT / 2)];
+            assert_eq!(contract.receive_split(strict, otp, split), Err(Error::InvalidReceiver));
+            assert_eq!(record(&contract, strict).otp_attempts, 1);
+            assert_eq!(contract.receive_payment(strict, otp), Ok(ClaimOutcome::Paid));
+            assert_eq!(balance_of(accounts.bob), bob + 2 * AMOUNT);
+        }
+
+        #[ink::test]
+        fn defaults_report_the_deployment_constants() {
+            let mut contract = setup();
+            contract.set_expiry_period(1_000).expect("admin call failed");
+            contract.set_threshold_amount(1).expect("admin call failed");
+            assert_eq!(contract.default_expiry(), 86_400_000);
+            assert_eq!(contract.default_threshold(), 100_000_000_000_000);
+            assert_eq!(contract.default_expiry(), DEFAULT_EXPIRY_TIME);
+            assert_eq!(contract.default_threshold(), DEFAULT_THRESHOLD);
+        }
+
+        #[ink::test]
+        fn receiver_thresholds_are_listed_in_input_order() {
+            let mut contract = setup();
+            let accounts = accounts();
+            for (receiver, threshold) in [(accounts.bob, 2 * AMOUNT), (accounts.django, 1)] {
+                let set = contract.set_receiver_threshold(receiver, Some(threshold));
+                set.expect("admin call failed");
+            }
+
+            let receivers = vec![accounts.django, accounts.charlie, accounts.bob, accounts.charlie];
+            assert_eq!(
+                contract.get_receiver_thresholds(receivers),
+                Ok(vec![Some(1), None, Some(2 * AMOUNT), None])
+            );
+            assert_eq!(contract.get_receiver_thresholds(Vec::new()), Ok(Vec::new()));
+            let too_many = vec![accounts.bob; MAX_BATCH_SIZE as usize + 1];
+            assert_eq!(contract.get_receiver_thresholds(too_many), Err(Error::BatchTooLarge));
+        }
+
+        #[ink::test]
+        fn expected_amount_is_net_of_fee_for_the_receiver_only() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_fee(100, accounts.eve).expect("admin call failed");
+            let id = send(&mut contract, accounts.charlie, accounts.bob);
+            let net = AMOUNT - AMOUNT / 100;
+
+            set_caller(accounts.bob);
+            assert_eq!(contract.expected_amount(id), Ok(net));
+            set_caller(accounts.alice);
+            assert_eq!(contract.expected_amount(id), Ok(net));
+            for outsider in [accounts.charlie, accounts.django] {
+                set_caller(outsider);
+                assert_eq!(contract.expected_amount(id), Err(Error::InvalidReceiver));
+            }
+            let missing = Hash::from([7; 32]);
+            assert_eq!(contract.expected_amount(missing), Err(Error::PaymentRecordMissing));
+
+            // asking uses no attempt and the quoted amount is what lands
+            assert_eq!(record(&contract, id).otp_attempts, 1);
+            let bob = balance_of(accounts.bob);
+            let otp = otp_of(&contract, id);
+            assert_eq!(submit(&mut contract, id, otp), Ok(ClaimOutcome::Paid));
+            assert_eq!(balance_of(accounts.bob), bob + net);
+        }
+
+        #[ink::test]
+        fn attempts_limit_of_255_fails_cleanly_at_the_boundary() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_attempts_limit(u8::MAX).expect("admin call failed");
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+
+            for attempts_left in (1..u8::MAX).rev() {
+                assert_eq!(
+                    submit_wrong(&mut contract, id),
+                    Ok(ClaimOutcome::WrongOtp { attempts_left })
+                );
+            }
+            assert_eq!(record(&contract, id).otp_attempts, u8::MAX);
+
+            let alice = balance_of(accounts.alice);
+            assert_eq!(submit_wrong(&mut contract, id), Ok(ClaimOutcome::AttemptsExhausted));
+            assert!(contract.payment_records.get(id).is_none());
+            assert_eq!(contract.status_counts(), (0, 0, 0, 0, 1));
+            assert_eq!(balance_of(accounts.alice), alice + AMOUNT);
+            set_caller(accounts.bob);
+            assert_eq!(contract.receive_payment(id, OTP_MIN), Err(Error::PaymentRecordMissing));
+        }
+
+        #[ink::test]
+        fn can_send_reports_what_send_payment_would() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_max_amount(Some(3 * AMOUNT)).expect("admin call failed");
+            contract.set_max_tvl(Some(4 * AMOUNT)).expect("admin call failed");
+            set_caller(accounts.django);
+            contract.set_my_receive_cap(Some(AMOUNT)).expect("cap failed");
+            send_amount(&mut contract, accounts.alice, accounts.bob, 3 * AMOUNT)
+                .expect("send failed");
+
+            let cases = [
+                (zero_address(), AMOUNT, Err(Error::ZeroAddress)),
+                (contract_id(), AMOUNT, Err(Error::InvalidReceiver)),
+                (accounts.bob, 0, Err(Error::ZeroBalance)),
+                (accounts.bob, AMOUNT - 1, Err(Error::BelowThresholdValue)),
+                (accounts.bob, 3 * AMOUNT + 1, Err(Error::AboveMaxAmount)),
+                (accounts.django, 2 * AMOUNT, Err(Error::ExceedsReceiverCap)),
+                (accounts.bob, 2 * AMOUNT, Err(Error::TvlCapExceeded)),
+                (accounts.bob, AMOUNT, Ok(())),
+            ];
+            for (receiver, amount, expected) in cases {
+                set_caller(accounts.alice);
+                assert_eq!(contract.can_send(receiver, amount), expected);
+                let sent = send_amount(&mut contract, accounts.alice, receiver, amount);
+                assert_eq!(sent.map(|_| ()), expected);
+            }
+
+            // the pre-check created nothing, and pausing is reported first
+            assert_eq!(contract.status_counts(), (2, 0, 0, 0, 0));
+            contract.set_paused(true).expect("admin call failed");
+            assert_eq!(contract.can_send(accounts.bob, AMOUNT), Err(Error::ContractPaused));
+        }
+
+        #[ink::test]
+        fn cancel_fee_applies_to_cancels_but_not_expiry_refunds() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_fee(0, accounts.eve).expect("admin call failed");
+            let free = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            let alice = balance_of(accounts.alice);
+            assert_eq!(contract.cancel_payment(free), Ok(()));
+            assert_eq!(balance_of(accounts.alice), alice + AMOUNT);
+            assert_eq!(balance_of(accounts.eve), 0);
+
+            contract.set_cancel_fee(250).expect("admin call failed");
+            let cancelled = send(&mut contract, accounts.alice, accounts.bob);
+            let expiring = send(&mut contract, accounts.alice, accounts.bob);
+            let claimed_late = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            let alice = balance_of(accounts.alice);
+            assert_eq!(contract.cancel_payment(cancelled), Ok(()));
+            let fee = AMOUNT * 250 / 10_000;
+            assert_eq!(balance_of(accounts.alice), alice + AMOUNT - fee);
+            assert_eq!(balance_of(accounts.eve), fee);
+
+            advance_time(DEFAULT_EXPIRY_TIME + 1);
+            let otp = otp_of(&contract, claimed_late);
+            assert_eq!(submit(&mut contract, claimed_late, otp), Ok(ClaimOutcome::Expired));
+            set_caller(accounts.alice);
+            let alice = balance_of(accounts.alice);
+            assert_eq!(contract.get_refund(expiring), Ok(()));
+            assert_eq!(contract.get_refund(claimed_late), Ok(()));
+            assert_eq!(balance_of(accounts.alice), alice + 2 * AMOUNT);
+            assert_eq!(balance_of(accounts.eve), fee);
+            assert_eq!(contract.get_fee_breakdown(), (0, fee));
+            contract.check_invariants(&[free, cancelled, expiring, claimed_late]);
+        }
+
+        #[ink::test]
+        fn salt_getter_reproduces_the_next_otp() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let salt = contract.get_salt().expect("admin call failed");
+            assert_eq!(salt, contract.salt);
+
+            // an auditor holding the salt and block time can recompute the OTP
+            let mut input = now().to_be_bytes().to_vec();
+            input.extend_from_slice(&salt.to_be_bytes());
+            let mut output = <hash::Keccak256 as hash::HashOutput>::Type::default();
+            ink::env::hash_bytes::<hash::Keccak256>(&input, &mut output);
+            let mut sample = [0u8; 8];
+            sample.copy_from_slice(&output[..8]);
+            let span = u64::from(OTP_MAX - OTP_MIN) + 1;
+            let expected = OTP_MIN + (u64::from_be_bytes(sample) % span) as u32;
+
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            assert_eq!(otp_of(&contract, id), expected);
+            set_caller(accounts.alice);
+            assert_eq!(contract.get_salt(), Ok(salt + 1));
+            send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.alice);
+            assert_eq!(contract.get_salt(), Ok(salt + 2));
+
+            set_caller(accounts.bob);
+            assert_eq!(contract.get_salt(), Err(Error::InvalidCaller));
+        }
+
+        #[ink::test]
+        fn fees_never_go_to_the_zero_address() {
+            let mut contract = setup();
+            let accounts = accounts();
+            assert_eq!(contract.set_fee(100, zero_address()), Err(Error::ZeroAddress));
+            assert_eq!(contract.set_fee(0, zero_address()), Ok(()));
+            assert_eq!(contract.set_cancel_fee(100), Err(Error::ZeroAddress));
+            assert_eq!(contract.get_fee_config().0, 0);
+            contract.set_fee(0, accounts.eve).expect("admin call failed");
+            contract.set_cancel_fee(100).expect("admin call failed");
+            assert_eq!(contract.set_fee(0, zero_address()), Err(Error::ZeroAddress));
+
+            // a state that slipped past the setters charges nothing instead of burning
+            contract.fee_bps = 100;
+            contract.fee_recipient = zero_address();
+            let claimed = send(&mut contract, accounts.alice, accounts.bob);
+            let cancelled = send(&mut contract, accounts.alice, accounts.bob);
+            let bob = balance_of(accounts.bob);
+            let otp = otp_of(&contract, claimed);
+            assert_eq!(submit(&mut contract, claimed, otp), Ok(ClaimOutcome::Paid));
+            assert_eq!(balance_of(accounts.bob), bob + AMOUNT);
+            set_caller(accounts.alice);
+            let alice = balance_of(accounts.alice);
+            assert_eq!(contract.cancel_payment(cancelled), Ok(()));
+            assert_eq!(balance_of(accounts.alice), alice + AMOUNT);
+            assert_eq!(balance_of(zero_address()), 0);
+            assert_eq!(contract.get_fee_breakdown(), (0, 0));
+            contract.check_invariants(&[claimed, cancelled]);
+        }
+
+        #[ink::test]
+        fn has_pending_tracks_open_payments_for_the_receiver() {
+            let mut contract = setup();
+            let accounts = accounts();
+            set_caller(accounts.bob);
+            assert_eq!(contract.has_pending(accounts.bob), Ok(false));
+            let first = send(&mut contract, accounts.charlie, accounts.bob);
+            let second = send(&mut contract, accounts.charlie, accounts.bob);
+
+            set_caller(accounts.bob);
+            assert_eq!(contract.has_pending(accounts.bob), Ok(true));
+            contract.acknowledge_payment(first).expect("acknowledge failed");
+            assert_eq!(contract.has_pending(accounts.bob), Ok(true));
+            set_caller(accounts.django);
+            assert_eq!(contract.has_pending(accounts.django), Ok(false));
+            assert_eq!(contract.has_pending(accounts.bob), Err(Error::InvalidReceiver));
+            set_caller(accounts.alice);
+            assert_eq!(contract.has_pending(accounts.bob), Ok(true));
+
+            let otp = otp_of(&contract, first);
+            submit(&mut contract, first, otp).expect("claim failed");
+            assert_eq!(contract.has_pending(accounts.bob), Ok(true));
+            set_caller(accounts.charlie);
+            contract.cancel_payment(second).expect("cancel failed");
+            set_caller(accounts.bob);
+            assert_eq!(contract.has_pending(accounts.bob), Ok(false));
+        }
+
+        #[ink::test]
+        fn escrow_breakdown_splits_active_from_surplus() {
+            let mut contract = setup();
+            let accounts = accounts();
+            assert_eq!(contract.escrow_breakdown(), (0, 0));
+            let expired = send(&mut contract, accounts.alice, accounts.bob);
+            advance_time(DEFAULT_EXPIRY_TIME + 1);
+            let otp = otp_of(&contract, expired);
+            assert_eq!(submit(&mut contract, expired, otp), Ok(ClaimOutcome::Expired));
+
+            let waiting = send(&mut contract, accounts.alice, accounts.bob);
+            let acknowledged = send(&mut contract, accounts.alice, accounts.bob);
+            let paid = send(&mut contract, accounts.alice, accounts.bob);
+            let cancelled = send(&mut contract, accounts.alice, accounts.bob);
+            set_caller(accounts.bob);
+            contract.acknowledge_payment(acknowledged).expect("acknowledge failed");
+            let otp = otp_of(&contract, paid);
+            submit(&mut contract, paid, otp).expect("claim failed");
+            set_caller(accounts.alice);
+            contract.cancel_payment(cancelled).expect("cancel failed");
+
+            // the expired payment still awaits its refund, so it sits in the surplus
+            assert_eq!(contract.escrow_breakdown(), (2 * AMOUNT, AMOUNT));
+            assert_eq!(balance_of(contract_id()), 3 * AMOUNT);
+            contract.get_refund(expired).expect("refund failed");
+            assert_eq!(contract.escrow_breakdown(), (2 * AMOUNT, 0));
+            contract.check_invariants(&[expired, waiting, acknowledged, paid, cancelled]);
+        }
+
+        #[ink::test]
+        fn preview_receive_error_matches_the_claim_without_using_attempts() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let late = send(&mut contract, accounts.alice, accounts.bob);
+            let (otp, wrong) = (otp_of(&contract, id), wrong_otp(&contract, id));
+
+            set_caller(accounts.bob);
+            assert_eq!(contract.preview_receive_error(id, wrong), Some(Error::WrongOTP));
+            assert_eq!(contract.preview_receive_error(id, otp), None);
+            set_caller(accounts.alice);
+            assert_eq!(contract.preview_receive_error(id, otp), None);
+            set_caller(accounts.charlie);
+            assert_eq!(contract.preview_receive_error(id, otp), Some(Error::InvalidReceiver));
+            assert_eq!(record(&contract, id).otp_attempts, 1);
+
+            assert_eq!(submit(&mut contract, id, otp), Ok(ClaimOutcome::Paid));
+            assert_eq!(
+                contract.preview_receive_error(id, otp),
+                Some(Error::AlreadyReceivedPayment)
+            );
+
+            advance_time(DEFAULT_EXPIRY_TIME + 1);
+            set_caller(accounts.bob);
+            let otp = otp_of(&contract, late);
+            assert_eq!(
+                contract.preview_receive_error(late, otp),
+                Some(Error::TimeLimitExceeded)
+            );
+            assert_eq!(submit(&mut contract, late, otp), Ok(ClaimOutcome::Expired));
+        }
+
+        #[ink::test]
+        fn attempts_limit_getter_follows_the_setter() {
+            let mut contract = setup();
+            let accounts = accounts();
+            assert_eq!(contract.get_attempts_limit(), ATTEMPTS_LIMIT);
+            assert_eq!(contract.set_attempts_limit(5), Ok(()));
+            assert_eq!(contract.get_attempts_limit(), 5);
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            assert_eq!(record(&contract, id).attempts_limit, contract.get_attempts_limit());
+
+            set_caller(accounts.alice);
+            assert_eq!(contract.set_attempts_limit(0), Err(Error::InvalidAttemptsLimit));
+            set_caller(accounts.bob);
+            assert_eq!(contract.set_attempts_limit(1), Err(Error::InvalidCaller));
+            assert_eq!(contract.get_attempts_limit(), 5);
+        }
+
+        #[ink::test]
+        fn mul_div_is_exact_when_the_product_overflows() {
+            let half = 1u128 << 127;
+            assert_eq!(mul_div(half, half, half + 1, RoundingMode::Floor), Ok(half - 1));
+            assert_eq!(mul_div(half, half, half + 1, RoundingMode::Ceil), Ok(half));
+            let max = u128::MAX;
+            assert_eq!(mul_div(max, max, max, RoundingMode::Floor), Ok(max));
+            assert_eq!(
+                mul_div(u128::MAX, 2, 3, RoundingMode::Floor),
+                Ok(226_854_911_280_625_642_308_916_404_954_512_140_970)
+            );
+            assert_eq!(mul_div(u128::MAX, 3, 2, RoundingMode::Floor), Err(Error::Overflow));
+            assert_eq!(mul_div(u128::MAX, 1, 1, RoundingMode::Ceil), Ok(u128::MAX));
+            assert_eq!(mul_div(1, 1, 0, RoundingMode::Floor), Err(Error::DivByZero));
+        }
+
+        #[ink::test]
+        fn mul_div_rounds_per_mode() {
+            let rounded = |value, mode| mul_div(value, 1, 3, mode);
+            assert_eq!(rounded(4, RoundingMode::Floor), Ok(1));
+            assert_eq!(rounded(4, RoundingMode::Ceil), Ok(2));
+            assert_eq!(rounded(4, RoundingMode::HalfUp), Ok(1));
+            assert_eq!(rounded(5, RoundingMode::HalfUp), Ok(2));
+            assert_eq!(mul_div(1, 1, 2, RoundingMode::HalfUp), Ok(1));
+            assert_eq!(mul_div(12_345, 250, BPS_DENOMINATOR, RoundingMode::Floor), Ok(308));
+        }
+
+        #[ink::test]
+        fn verified_receivers_keep_the_global_caps_unless_given_their_own() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_kyc_status(accounts.bob, true).expect("admin call failed");
+            contract.set_max_tvl(Some(AMOUNT)).expect("admin call failed");
+            let options = PaymentOptions::default();
+            send(&mut contract, accounts.alice, accounts.bob);
+            let over = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options.clone());
+            assert_eq!(over, Err(Error::TvlCapExceeded));
+
+            set_caller(accounts.alice);
+            contract.set_verified_limits(None, Some(2 * AMOUNT)).expect("admin call failed");
+            send(&mut contract, accounts.alice, accounts.bob);
+            let over = send_with(&mut contract, accounts.alice, accounts.charlie, AMOUNT, options);
+            assert_eq!(over, Err(Error::TvlCapExceeded));
+        }
+
+        #[ink::test]
+        fn dashboard_fields_match_their_getters() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_fee(100, accounts.eve).expect("admin call failed");
+            let paid = send(&mut contract, accounts.alice, accounts.bob);
+            let cancelled = send(&mut contract, accounts.alice, accounts.bob);
+            let reissued = send(&mut contract, accounts.charlie, accounts.django);
+            let otp = otp_of(&contract, paid);
+            submit_wrong(&mut contract, paid).expect("claim failed");
+            submit(&mut contract, paid, otp).expect("claim failed");
+            set_caller(accounts.alice);
+            contract.cancel_payment(cancelled).expect("cancel failed");
+            set_caller(accounts.charlie);
+            contract.reissue_payment(reissued).expect("reissue failed");
+
+            let dashboard = contract.dashboard();
+            let (fee_bps, fee_recipient, _) = contract.get_fee_config();
+            let (waiting, success, refunded, expired, all_attempts_failed) =
+                contract.status_counts();
+            assert_eq!(dashboard.admin, contract.admin);
+            assert_eq!(dashboard.threshold_value, contract.threshold_value);
+            assert_eq!(dashboard.expiry_time, contract.expiry_time);
+            assert_eq!((dashboard.fee_bps, dashboard.fee_recipient), (fee_bps, fee_recipient));
+            assert_eq!(dashboard.paused, contract.is_paused());
+            assert_eq!(
+                (dashboard.waiting, dashboard.success, dashboard.refunded),
+                (waiting, success, refunded)
+            );
+            assert_eq!(
+                (dashboard.expired, dashboard.all_attempts_failed),
+                (expired, all_attempts_failed)
+            );
+            assert_eq!((dashboard.forfeited, dashboard.replaced), (0, 1));
+            assert_eq!(dashboard.total_otp_attempts, contract.get_total_attempts());
+            assert_eq!(dashboard.total_volume, contract.get_total_volume());
+            assert_eq!(dashboard.outstanding, contract.get_tracked_escrow());
+
+            assert_eq!((waiting, success, refunded), (1, 1, 1));
+            assert_eq!(dashboard.total_otp_attempts, 2);
+            assert_eq!(dashboard.total_volume, 3 * AMOUNT);
+            assert_eq!(dashboard.outstanding, AMOUNT);
+        }
+
+        #[ink::test]
+        fn untried_payment_cannot_be_cancelled_under_min_attempts() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_min_attempts_before_refund(1).expect("admin call failed");
+            let untried = send(&mut contract, accounts.alice, accounts.bob);
+            let tried = send(&mut contract, accounts.alice, accounts.bob);
+            submit_wrong(&mut contract, tried).expect("claim failed");
+
+            set_caller(accounts.alice);
+            assert_eq!(contract.cancel_payment(untried), Err(Error::NotAllowed));
+            assert_eq!(
+                contract.cancel_payment_batch(vec![untried, tried]),
+                Ok(vec![Err(Error::NotAllowed), Ok(())])
+            );
+            assert_eq!(record(&contract, untried).status, PaymentStatus::Waiting);
+            assert_eq!(record(&contract, tried).status, PaymentStatus::Refunded);
+        }
+
+        #[ink::test]
+        fn staged_threshold_applies_only_after_the_delay() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let delay = 1_000;
+            contract.set_config_delay(delay).expect("admin call failed");
+            contract.set_threshold_amount(2 * AMOUNT).expect("admin call failed");
+            assert_eq!(contract.threshold_value, DEFAULT_THRESHOLD);
+            assert_eq!(contract.get_pending_config().threshold_value, Some(2 * AMOUNT));
+            assert_eq!(contract.get_pending_config().effective_at, START + delay);
+            send(&mut contract, accounts.alice, accounts.bob);
+
+            advance_time(delay - 1);
+            assert_eq!(contract.apply_pending_config(), Err(Error::TimelockNotElapsed));
+            advance_time(1);
+            // anyone may apply once the delay has passed
+            set_caller(accounts.django);
+            assert_eq!(contract.apply_pending_config(), Ok(()));
+            assert_eq!(contract.threshold_value, 2 * AMOUNT);
+            let below = send_amount(&mut contract, accounts.alice, accounts.bob, AMOUNT);
+            assert_eq!(below, Err(Error::BelowThresholdValue));
+            assert_eq!(contract.apply_pending_config(), Err(Error::NotAllowed));
+        }
+
+        #[ink::test]
+        fn staged_windows_apply_together_after_the_delay() {
+            let mut contract = setup();
+            let delay = 1_000;
+            contract.set_config_delay(delay).expect("admin call failed");
+            contract.set_windows(5_000, 2_000).expect("admin call failed");
+            assert_eq!((contract.expiry_time, contract.refund_delay), (DEFAULT_EXPIRY_TIME, 0));
+            let pending = contract.get_pending_config();
+            assert_eq!((pending.expiry_time, pending.refund_delay), (Some(5_000), Some(2_000)));
+
+            advance_time(delay - 1);
+            assert_eq!(contract.apply_pending_config(), Err(Error::TimelockNotElapsed));
+            advance_time(1);
+            let before = emitted::<ConfigChanged>();
+            assert_eq!(contract.apply_pending_config(), Ok(()));
+            assert_eq!((contract.expiry_time, contract.refund_delay), (5_000, 2_000));
+            assert_eq!(emitted::<ConfigChanged>(), before + 1);
+        }
+
+        #[ink::test]
+        fn compare_and_set_checks_the_staged_threshold() {
+            let mut contract = setup();
+            contract.set_config_delay(1_000).expect("admin call failed");
+            contract.set_threshold_amount(2 * AMOUNT).expect("admin call failed");
+
+            // the live value is stale once another change is staged
+            assert_eq!(
+                contract.compare_and_set_threshold(DEFAULT_THRESHOLD, 3 * AMOUNT),
+                Err(Error::StaleValue)
+            );
+            assert_eq!(contract.get_pending_config().threshold_value, Some(2 * AMOUNT));
+            assert_eq!(contract.compare_and_set_threshold(2 * AMOUNT, 3 * AMOUNT), Ok(()));
+            assert_eq!(contract.get_pending_config().threshold_value, Some(3 * AMOUNT));
+        }
+
+        #[ink::test]
+        fn reference_threshold_is_staged_under_the_delay() {
+            let mut contract = setup();
+            let accounts = accounts();
+            contract.set_config_delay(1_000).expect("admin call failed");
+            contract.set_reference_threshold(Some(accounts.frank), 5).expect("admin call failed");
+            assert_eq!((contract.oracle, contract.threshold_in_reference), (None, 0));
+            assert_eq!(
+                contract.get_pending_config().reference_threshold,
+                Some((Some(accounts.frank), 5))
+            );
+
+            advance_time(1_000);
+            assert_eq!(contract.apply_pending_config(), Ok(()));
+            assert_eq!(contract.oracle, Some(accounts.frank));
+            assert_eq!(contract.threshold_in_reference, 5);
+        }
+
+        #[ink::test]
+        fn wrong_otp_warns_only_within_warn_before_of_the_deadline() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let expires_at = record(&contract, id).expires_at;
+
+            // off by default, even right at the deadline
+            test::set_block_timestamp::<Env>(expires_at - 1);
+            submit_wrong(&mut contract, id).expect("claim failed");
+            assert_eq!(emitted::<ClaimExpiringSoon>(), 0);
+
+            set_caller(accounts.alice);
+            contract.set_warn_before(1_000).expect("admin call failed");
+            let id = send(&mut contract, accounts.alice, accounts.bob);
+            let expires_at = record(&contract, id).expires_at;
+            test::set_block_timestamp::<Env>(expires_at - 1_002);
+            submit_wrong(&mut contract, id).expect("claim failed");
+            assert_eq!(emitted::<ClaimExpiringSoon>(), 0);
+
+            // submit advances the clock by 1, leaving exactly warn_before
+            assert_eq!(
+                submit_wrong(&mut contract, id),
+                Ok(ClaimOutcome::WrongOtp { attempts_left: 1 })
+            );
+            assert_eq!(emitted::<ClaimExpiringSoon>(), 1);
+            let warning = contract
+                .payment_events(id)
+                .expect("payment has no log")
+                .into_iter()
+                .filter(|entry| entry.0 == ClaimExpiringSoon::KIND)
+                .count();
+            assert_eq!(warning, 1);
+        }
+
+        #[ink::test]
+        fn default_limit_fails_the_payment_on_the_third_wrong_otp() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let payment_id = send(&mut contract, accounts.alice, accounts.bob);
+            assert_eq!(record(&contract, payment_id).attempts_limit, ATTEMPTS_LIMIT);
+
+            assert_eq!(
+                submit_wrong(&mut contract, payment_id),
+                Ok(ClaimOutcome::WrongOtp { attempts_left: 2 })
+            );
+            assert_eq!(
+                submit_wrong(&mut contract, payment_id),
+                Ok(ClaimOutcome::WrongOtp { attempts_left: 1 })
+            );
+            assert_eq!(record(&contract, payment_id).status, PaymentStatus::Waiting);
+            assert_eq!(
+                submit_wrong(&mut contract, payment_id),
+                Ok(ClaimOutcome::AttemptsExhausted)
+            );
+            assert!(contract.payment_records.get(payment_id).is_none());
+        }
+
+        #[ink::test]
+        fn stricter_per_payment_limit_fails_sooner_than_the_global_one() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let options = PaymentOptions {
+                attempts: Some(1),
+                ..PaymentOptions::default()
+            };
+            let strict = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+            let lenient = send(&mut contract, accounts.alice, accounts.bob);
+
+            assert_eq!(
+                submit_wrong(&mut contract, strict),
+                Ok(ClaimOutcome::AttemptsExhausted)
+            );
+            assert!(contract.payment_records.get(strict).is_none());
+            assert_eq!(
+                submit_wrong(&mut contract, lenient),
+                Ok(ClaimOutcome::WrongOtp { attempts_left: 2 })
+            );
+            assert_eq!(record(&contract, lenient).status, PaymentStatus::Waiting);
+        }
+
+        #[ink::test]
+        fn per_payment_limit_must_lie_within_the_global_one() {
+            let mut contract = setup();
+            let accounts = accounts();
+            for attempts in [0, ATTEMPTS_LIMIT + 1] {
+                let options = PaymentOptions {
+                    attempts: Some(attempts),
+                    ..PaymentOptions::default()
+                };
+                assert_eq!(
+                    send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options),
+                    Err(Error::InvalidAttemptsLimit)
+                );
+            }
+            let options = PaymentOptions {
+                attempts: Some(ATTEMPTS_LIMIT),
+                ..PaymentOptions::default()
+            };
+            let payment_id = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+            assert_eq!(record(&contract, payment_id).attempts_limit, ATTEMPTS_LIMIT);
+        }
+
+        #[ink::test]
+        fn reissue_never_loosens_the_attempts_limit() {
+            let mut contract = setup();
+            let accounts = accounts();
+            let options = PaymentOptions {
+                attempts: Some(2),
+                ..PaymentOptions::default()
+            };
+            let first = send_with(&mut contract, accounts.alice, accounts.bob, AMOUNT, options)
+                .expect("send failed");
+            let second = send(&mut contract, accounts.alice, accounts.bob);
+
+            set_caller(accounts.alice);
+            contract.set_attempts_limit(1).expect("admin call failed");
+            let first = contract.reissue_payment(first).expect("reissue failed");
+            let second = contract.reissue_payment(second).expect("reissue failed");
+            assert_eq!(record(&contract, first).attempts_limit, 1);
+            assert_eq!(record(&contract, second).attempts_limit, 1);
+        }
+    }
+
+    #[cfg(all(test, feature = "e2e-tests"))]
+    mod e2e_tests {
+        use super::*;
+        use ink_e2e::{ChainBackend, ContractsBackend};
+        use receiver_hooks::{ReceiverHooks, ReceiverHooksRef};
+        use reentrant_sender::{ReentrantSender, ReentrantSenderRef};
+        use scale::Decode;
+
+        type Env = DefaultEnvironment;
+        type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+        const AMOUNT: Balance = DEFAULT_THRESHOLD;
+
+        // Payload of every `E` the payment contract emitted during a call
+        fn events_of<E: ink::env::Event + Decode>(
+            contract: AccountId,
+            events: Vec<ink_e2e::events::EventWithTopics<ink_e2e::events::ContractEmitted<Env>>>,
+        ) -> Vec<E> {
+            let signature = E::SIGNATURE_TOPIC.expect("event is anonymous");
+            events
+                .into_iter()
+                .filter(|emitted| emitted.event.contract == contract)
+                .filter(|emitted| emitted.topics.first().is_some_and(|t| t.0 == signature))
+                .map(|emitted| E::decode(&mut &emitted.event.data[..]).expect("bad event"))
+                .collect()
+        }
+
+        #[ink_e2e::test]
+        async fn refund_to_a_contract_sender_is_paid_once<Client: E2EBackend>(
+            mut client: Client,
+        ) -> E2EResult<()> {
+            let admin = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let mut constructor = PaymentContractRef::new(admin);
+            let payments = client
+                .instantiate("secure_payment_system", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let mut payment_calls = ink_e2e::create_call_builder::<PaymentContract>(payments);
+            // refunds fall due one millisecond after sending
+            client
+                .call(&ink_e2e::alice(), &payment_calls.set_windows(1, 0))
+                .submit()
+                .await
+                .expect("set_windows failed");
+
+            let mut constructor = ReentrantSenderRef::new(payments);
+            let sender = client
+                .instantiate("reentrant_sender", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let mut sender_calls = sender.call_builder::<ReentrantSender>();
+
+            let receiver = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let sent = client
+                .call(&ink_e2e::alice(), &sender_calls.send(receiver))
+                .value(AMOUNT)
+                .submit()
+                .await
+                .expect("send failed");
+            let requested: Vec<SecurePaymentRequested> =
+                events_of(payments, sent.contract_emitted_events()?);
+            assert_eq!(requested.len(), 1);
+            assert_eq!(requested[0].sender, sender.account_id);
+            let payment_id = requested[0].payment_id;
+            // dry runs see the latest block's timestamp, so let one block pass the deadline
+            client
+                .call(&ink_e2e::alice(), &payment_calls.set_symbol(DEFAULT_SYMBOL))
+                .submit()
+                .await
+                .expect("set_symbol failed");
+
+            let before = client.free_balance(sender.account_id).await?;
+            let refunded = client
+                .call(&ink_e2e::alice(), &sender_calls.refund_twice(payment_id))
+                .submit()
+                .await
+                .expect("refund_twice failed");
+            let events = refunded.contract_emitted_events()?;
+            assert_eq!(refunded.return_value(), (true, false));
+            assert_eq!(events_of::<PaymentRefunded>(payments, events).len(), 1);
+            assert_eq!(client.free_balance(sender.account_id).await?, before + AMOUNT);
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn wrong_otps_persist_until_the_payment_fails<Client: E2EBackend>(
+            mut client: Client,
+        ) -> E2EResult<()> {
+            let admin = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let mut constructor = PaymentContractRef::new(admin);
+            let payments = client
+                .instantiate("secure_payment_system", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let mut calls = ink_e2e::create_call_builder::<PaymentContract>(payments);
+
+            let receiver = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let sent = client
+                .call(&ink_e2e::alice(), &calls.send_payment(receiver, AMOUNT))
+                .value(AMOUNT)
+                .submit()
+                .await
+                .expect("send failed");
+            let requested: Vec<SecurePaymentRequested> =
+                events_of(payments, sent.contract_emitted_events()?);
+            let (payment_id, otp) = (requested[0].payment_id, requested[0].otp);
+            let wrong = if otp == OTP_MAX { OTP_MIN } else { otp + 1 };
+
+            let expected = [
+                ClaimOutcome::WrongOtp { attempts_left: 2 },
+                ClaimOutcome::WrongOtp { attempts_left: 1 },
+                ClaimOutcome::AttemptsExhausted,
+            ];
+            for (attempt, outcome) in expected.into_iter().enumerate() {
+                // dry runs see the latest block's timestamp, so let one block pass the gap
+                client
+                    .call(&ink_e2e::alice(), &calls.set_symbol(DEFAULT_SYMBOL))
+                    .submit()
+                    .await
+                    .expect("set_symbol failed");
+                let claimed = client
+                    .call(&ink_e2e::bob(), &calls.receive_payment(payment_id, wrong))
+                    .submit()
+                    .await
+                    .expect("receive_payment failed");
+                assert_eq!(claimed.return_value(), Ok(outcome));
+
+                let total = client
+                    .call(&ink_e2e::alice(), &calls.get_total_attempts())
+                    .dry_run()
+                    .await?;
+                assert_eq!(total.return_value(), attempt as u64 + 1);
+            }
+
+            let purged = client
+                .call(&ink_e2e::alice(), &calls.counterparties(payment_id))
+                .dry_run()
+                .await?;
+            assert_eq!(purged.return_value(), Err(Error::PaymentRecordMissing));
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn failed_fee_transfer_reverts_the_whole_cancel_batch<Client: E2EBackend>(
+            mut client: Client,
+        ) -> E2EResult<()> {
+            let admin = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let mut constructor = PaymentContractRef::new(admin);
+            let payments = client
+                .instantiate("secure_payment_system", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let mut calls = ink_e2e::create_call_builder::<PaymentContract>(payments);
+
+            // a one-unit fee to an account that does not exist yet is below the existential
+            // deposit, so the fee transfer of every cancel fails
+            let amount: Balance = 10_000;
+            let fee_recipient = AccountId::from([0x7E; 32]);
+            client
+                .call(&ink_e2e::alice(), &calls.set_threshold_amount(amount))
+                .submit()
+                .await
+                .expect("set_threshold_amount failed");
+            client
+                .call(&ink_e2e::alice(), &calls.set_fee(0, fee_recipient))
+                .submit()
+                .await
+                .expect("set_fee failed");
+            client
+                .call(&ink_e2e::alice(), &calls.set_cancel_fee(1))
+                .submit()
+                .await
+                .expect("set_cancel_fee failed");
+
+            let receiver = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let mut ids = Vec::new();
+            for _ in 0..2 {
+                let sent = client
+                    .call(&ink_e2e::alice(), &calls.send_payment(receiver, amount))
+                    .value(amount)
+                    .submit()
+                    .await
+                    .expect("send failed");
+                let requested: Vec<SecurePaymentRequested> =
+                    events_of(payments, sent.contract_emitted_events()?);
+                ids.push(requested[0].payment_id);
+            }
+
+            let cancelled = client
+                .call(&ink_e2e::alice(), &calls.cancel_payment_batch(ids.clone()))
+                .submit()
+                .await
+                .expect("cancel_payment_batch failed");
+            assert_eq!(cancelled.return_value(), Err(Error::TransferFailed));
+
+            let records = client
+                .call(&ink_e2e::alice(), &calls.get_records(ids))
+                .dry_run()
+                .await?
+                .return_value()
+                .expect("get_records failed");
+            for record in records {
+                let record = record.expect("record missing");
+                assert_eq!(record.status, PaymentStatus::Waiting);
+            }
+            let fees = client
+                .call(&ink_e2e::alice(), &calls.get_fee_breakdown())
+                .dry_run()
+                .await?;
+            assert_eq!(fees.return_value(), (0, 0));
+            let escrow = client
+                .call(&ink_e2e::alice(), &calls.get_tracked_escrow())
+                .dry_run()
+                .await?;
+            assert_eq!(escrow.return_value(), 2 * amount);
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn tick_reports_a_refund_to_a_removed_sender<Client: E2EBackend>(
+            mut client: Client,
+        ) -> E2EResult<()> {
+            let admin = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let mut constructor = PaymentContractRef::new(admin);
+            let payments = client
+                .instantiate("secure_payment_system", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let mut payment_calls = ink_e2e::create_call_builder::<PaymentContract>(payments);
+            // below the existential deposit, so the refund cannot recreate the removed sender
+            let amount: Balance = 10_000;
+            client
+                .call(&ink_e2e::alice(), &payment_calls.set_threshold_amount(amount))
+                .submit()
+                .await
+                .expect("set_threshold_amount failed");
+            client
+                .call(&ink_e2e::alice(), &payment_calls.set_windows(1, 0))
+                .submit()
+                .await
+                .expect("set_windows failed");
+
+            let mut constructor = ReentrantSenderRef::new(payments);
+            let sender = client
+                .instantiate("reentrant_sender", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let mut sender_calls = sender.call_builder::<ReentrantSender>();
+            let receiver = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let sent = client
+                .call(&ink_e2e::alice(), &sender_calls.send(receiver))
+                .value(amount)
+                .submit()
+                .await
+                .expect("send failed");
+            let requested: Vec<SecurePaymentRequested> =
+                events_of(payments, sent.contract_emitted_events()?);
+            let payment_id = requested[0].payment_id;
+            client
+                .call(&ink_e2e::alice(), &sender_calls.terminate())
+                .submit()
+                .await
+                .expect("terminate failed");
+
+            let ticked = client
+                .call(&ink_e2e::alice(), &payment_calls.tick(1))
+                .submit()
+                .await
+                .expect("tick failed");
+            let failed: Vec<RefundFailed> =
+                events_of(payments, ticked.contract_emitted_events()?);
+            assert_eq!(ticked.return_value(), Ok(1));
+            assert_eq!(failed.len(), 1);
+            assert_eq!(failed[0].recipient, sender.account_id);
+            assert_eq!((failed[0].payment_id, failed[0].amount), (payment_id, amount));
+
+            // the record is untouched and the payment waits at the back of the queue
+            let records = client
+                .call(&ink_e2e::alice(), &payment_calls.get_records(vec![payment_id]))
+                .dry_run()
+                .await?
+                .return_value()
+                .expect("get_records failed");
+            let record = records[0].as_ref().expect("record missing");
+            assert_eq!(record.status, PaymentStatus::Waiting);
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn notifier_is_called_only_for_its_receiver<Client: E2EBackend>(
+            mut client: Client,
+        ) -> E2EResult<()> {
+            let admin = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let mut constructor = PaymentContractRef::new(admin);
+            let payments = client
+                .instantiate("secure_payment_system", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let mut payment_calls = ink_e2e::create_call_builder::<PaymentContract>(payments);
+            let mut constructor = ReceiverHooksRef::new(true);
+            let hooks = client
+                .instantiate("receiver_hooks", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let hook_calls = hooks.call_builder::<ReceiverHooks>();
+
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+            client
+                .call(&ink_e2e::bob(), &payment_calls.register_notifier(hooks.account_id))
+                .submit()
+                .await
+                .expect("register_notifier failed");
+            // a notifier that is not a contract cannot block sends to charlie
+            client
+                .call(&ink_e2e::charlie(), &payment_calls.register_notifier(dave))
+                .submit()
+                .await
+                .expect("register_notifier failed");
+
+            let sent = client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(bob, AMOUNT))
+                .value(AMOUNT)
+                .submit()
+                .await
+                .expect("send failed");
+            let requested: Vec<SecurePaymentRequested> =
+                events_of(payments, sent.contract_emitted_events()?);
+            let to_charlie = client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(charlie, AMOUNT))
+                .value(AMOUNT)
+                .submit()
+                .await
+                .expect("send failed");
+            assert_eq!(to_charlie.return_value(), Ok(()));
+
+            let notified = client
+                .call(&ink_e2e::alice(), &hook_calls.last_notification())
+                .dry_run()
+                .await?;
+            assert_eq!(
+                notified.return_value(),
+                Some((requested[0].payment_id, admin, AMOUNT, Vec::new()))
+            );
+
+            // once cleared, bob's payments no longer reach the notifier
+            client
+                .call(&ink_e2e::bob(), &payment_calls.clear_notifier())
+                .submit()
+                .await
+                .expect("clear_notifier failed");
+            client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(bob, AMOUNT))
+                .value(AMOUNT)
+                .submit()
+                .await
+                .expect("send failed");
+            let notifications = client
+                .call(&ink_e2e::alice(), &hook_calls.notifications())
+                .dry_run()
+                .await?;
+            assert_eq!(notifications.return_value(), 1);
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn validator_rejects_only_with_an_explicit_no<Client: E2EBackend>(
+            mut client: Client,
+        ) -> E2EResult<()> {
+            let admin = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let mut constructor = PaymentContractRef::new(admin);
+            let payments = client
+                .instantiate("secure_payment_system", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let mut payment_calls = ink_e2e::create_call_builder::<PaymentContract>(payments);
+            let mut constructor = ReceiverHooksRef::new(true);
+            let hooks = client
+                .instantiate("receiver_hooks", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let mut hook_calls = hooks.call_builder::<ReceiverHooks>();
+
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let charlie = ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie);
+            let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+            let validator = Some(hooks.account_id);
+            client
+                .call(&ink_e2e::bob(), &payment_calls.set_acceptance_validator(validator))
+                .submit()
+                .await
+                .expect("set_acceptance_validator failed");
+            // a validator that cannot answer accepts, so it cannot block sends to charlie
+            client
+                .call(&ink_e2e::charlie(), &payment_calls.set_acceptance_validator(Some(dave)))
+                .submit()
+                .await
+                .expect("set_acceptance_validator failed");
+
+            let accepted = client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(bob, AMOUNT))
+                .value(AMOUNT)
+                .submit()
+                .await
+                .expect("send failed");
+            assert_eq!(accepted.return_value(), Ok(()));
+            let unanswered = client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(charlie, AMOUNT))
+                .value(AMOUNT)
+                .submit()
+                .await
+                .expect("send failed");
+            assert_eq!(unanswered.return_value(), Ok(()));
+
+            client
+                .call(&ink_e2e::bob(), &hook_calls.set_accept(false))
+                .submit()
+                .await
+                .expect("set_accept failed");
+            let rejected = client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(bob, AMOUNT))
+                .value(AMOUNT)
+                .dry_run()
+                .await?;
+            assert_eq!(rejected.return_value(), Err(Error::ReceiverRejected));
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn pegged_threshold_follows_the_oracle_rate<Client: E2EBackend>(
+            mut client: Client,
+        ) -> E2EResult<()> {
+            let admin = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let mut constructor = PaymentContractRef::new(admin);
+            let payments = client
+                .instantiate("secure_payment_system", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let mut payment_calls = ink_e2e::create_call_builder::<PaymentContract>(payments);
+            let mut constructor = ReceiverHooksRef::new(true);
+            let oracle = client
+                .instantiate("receiver_hooks", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let mut oracle_calls = oracle.call_builder::<ReceiverHooks>();
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+
+            // one reference unit per native unit: the threshold stays AMOUNT
+            let pegged = payment_calls.set_reference_threshold(Some(oracle.account_id), AMOUNT);
+            client
+                .call(&ink_e2e::alice(), &pegged)
+                .submit()
+                .await
+                .expect("set_reference_threshold failed");
+            let sent = client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(bob, AMOUNT))
+                .value(AMOUNT)
+                .submit()
+                .await
+                .expect("send failed");
+            assert_eq!(sent.return_value(), Ok(()));
+
+            // at two native units per reference unit it doubles
+            client
+                .call(&ink_e2e::alice(), &oracle_calls.set_rate(2))
+                .submit()
+                .await
+                .expect("set_rate failed");
+            let threshold = client
+                .call(&ink_e2e::alice(), &payment_calls.effective_threshold(admin, bob))
+                .dry_run()
+                .await?;
+            assert_eq!(threshold.return_value(), Ok(2 * AMOUNT));
+            let below = client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(bob, AMOUNT))
+                .value(AMOUNT)
+                .dry_run()
+                .await?;
+            assert_eq!(below.return_value(), Err(Error::BelowThresholdValue));
+            let at = client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(bob, 2 * AMOUNT))
+                .value(2 * AMOUNT)
+                .dry_run()
+                .await?;
+            assert_eq!(at.return_value(), Ok(()));
+
+            // an oracle that cannot answer fails the send closed
+            let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+            client
+                .call(&ink_e2e::alice(), &payment_calls.set_reference_threshold(Some(dave), AMOUNT))
+                .submit()
+                .await
+                .expect("set_reference_threshold failed");
+            let unavailable = client
+                .call(&ink_e2e::alice(), &payment_calls.send_payment(bob, 2 * AMOUNT))
+                .value(2 * AMOUNT)
+                .dry_run()
+                .await?;
+            assert_eq!(unavailable.return_value(), Err(Error::OracleUnavailable));
+            Ok(())
+        }
+
+        #[ink_e2e::test]
+        async fn notifier_receives_the_senders_callback_data<Client: E2EBackend>(
+            mut client: Client,
+        ) -> E2EResult<()> {
+            let admin = ink_e2e::account_id(ink_e2e::AccountKeyring::Alice);
+            let mut constructor = PaymentContractRef::new(admin);
+            let payments = client
+                .instantiate("secure_payment_system", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let mut payment_calls = ink_e2e::create_call_builder::<PaymentContract>(payments);
+            let mut constructor = ReceiverHooksRef::new(true);
+            let hooks = client
+                .instantiate("receiver_hooks", &ink_e2e::alice(), &mut constructor)
+                .submit()
+                .await
+                .expect("instantiate failed");
+            let hook_calls = hooks.call_builder::<ReceiverHooks>();
+            client
+                .call(&ink_e2e::bob(), &payment_calls.register_notifier(hooks.account_id))
+                .submit()
+                .await
+                .expect("register_notifier failed");
+
+            let bob = ink_e2e::account_id(ink_e2e::AccountKeyring::Bob);
+            let options = PaymentOptions {
+                callback_data: b"invoice:42".to_vec(),
+                ..Default::default()
+            };
+            let sent = client
+                .call(
+                    &ink_e2e::alice(),
+                    &payment_calls.send_payment_with_options(bob, AMOUNT, options),
+                )
+                .value(AMOUNT)
+                .submit()
+                .await
+                .expect("send failed");
+            let payment_id = sent.return_value().expect("send refused");
+
+            let notified = client
+                .call(&ink_e2e::alice(), &hook_calls.last_notification())
+                .dry_run()
+                .await?;
+            assert_eq!(
+                notified.return_value(),
+                Some((payment_id, admin, AMOUNT, b"invoice:42".to_vec()))
+            );
+            Ok(())
+        }
+    }
 } 
 
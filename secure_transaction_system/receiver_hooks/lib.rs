@@ -0,0 +1,80 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::receiver_hooks::{ReceiverHooks, ReceiverHooksRef};
+
+/// Notifier, acceptance validator and price oracle used by the payment contract's e2e
+/// tests. It records every `on_payment_received` call so the tests can read back what was
+/// forwarded, answers `accept_payment` with a switch the tests flip, and converts
+/// reference amounts at a rate the tests set
+#[ink::contract]
+mod receiver_hooks {
+    use ink::prelude::vec::Vec;
+
+    // payment id, sender, amount and callback data of one notification
+    pub type Notification = (Hash, AccountId, Balance, Vec<u8>);
+
+    #[ink(storage)]
+    pub struct ReceiverHooks {
+        notifications: u32,
+        last_notification: Option<Notification>,
+        accept: bool,
+        rate: Balance,
+    }
+
+    impl ReceiverHooks {
+        #[ink(constructor)]
+        pub fn new(accept: bool) -> Self {
+            Self {
+                notifications: 0,
+                last_notification: None,
+                accept,
+                rate: 1,
+            }
+        }
+
+        /// Called by the payment contract for each new payment to a receiver using this notifier
+        #[ink(message)]
+        pub fn on_payment_received(
+            &mut self,
+            payment_id: Hash,
+            sender: AccountId,
+            amount: Balance,
+            callback_data: Vec<u8>,
+        ) {
+            self.notifications += 1;
+            self.last_notification = Some((payment_id, sender, amount, callback_data));
+        }
+
+        /// Answer the payment contract gets before accepting a payment
+        #[ink(message)]
+        pub fn accept_payment(&self, _sender: AccountId, _amount: Balance) -> bool {
+            self.accept
+        }
+
+        #[ink(message)]
+        pub fn set_accept(&mut self, accept: bool) {
+            self.accept = accept;
+        }
+
+        /// Native units for `amount` reference units at the current rate
+        #[ink(message)]
+        pub fn to_native(&self, amount: Balance) -> Balance {
+            amount.saturating_mul(self.rate)
+        }
+
+        #[ink(message)]
+        pub fn set_rate(&mut self, rate: Balance) {
+            self.rate = rate;
+        }
+
+        #[ink(message)]
+        pub fn notifications(&self) -> u32 {
+            self.notifications
+        }
+
+        #[ink(message)]
+        pub fn last_notification(&self) -> Option<Notification> {
+            self.last_notification.clone()
+        }
+    }
+}
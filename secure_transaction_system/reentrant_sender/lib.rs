@@ -0,0 +1,64 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+pub use self::reentrant_sender::{ReentrantSender, ReentrantSenderRef};
+
+/// Sender contract used by the payment contract's e2e tests. It pays through the payment
+/// contract so that it is the refund recipient, then asks for the same refund twice or
+/// terminates so that the refund has nowhere to go
+#[ink::contract]
+mod reentrant_sender {
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+
+    #[ink(storage)]
+    pub struct ReentrantSender {
+        payments: AccountId,
+    }
+
+    impl ReentrantSender {
+        #[ink(constructor)]
+        pub fn new(payments: AccountId) -> Self {
+            Self { payments }
+        }
+
+        /// Forwards the attached value to `send_payment`, making this contract the sender
+        #[ink(message, payable)]
+        pub fn send(&mut self, receiver: AccountId) -> bool {
+            let amount = self.env().transferred_value();
+            let result = build_call::<Environment>()
+                .call(self.payments)
+                .transferred_value(amount)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("send_payment")))
+                        .push_arg(receiver)
+                        .push_arg(amount),
+                )
+                .returns::<Result<(), u8>>()
+                .try_invoke();
+            matches!(result, Ok(Ok(Ok(()))))
+        }
+
+        /// Calls `get_refund` twice in one transaction and reports which calls succeeded
+        #[ink(message)]
+        pub fn refund_twice(&mut self, payment_id: Hash) -> (bool, bool) {
+            (self.refund(payment_id), self.refund(payment_id))
+        }
+
+        /// Removes this contract, sending its balance to the caller
+        #[ink(message)]
+        pub fn terminate(&mut self) {
+            self.env().terminate_contract(self.env().caller())
+        }
+
+        fn refund(&self, payment_id: Hash) -> bool {
+            let result = build_call::<Environment>()
+                .call(self.payments)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_refund")))
+                        .push_arg(payment_id),
+                )
+                .returns::<Result<(), u8>>()
+                .try_invoke();
+            matches!(result, Ok(Ok(Ok(()))))
+        }
+    }
+}